@@ -7,7 +7,7 @@ function build_recorder_release() {
         wasm-bindgen ./target/wasm32-unknown-unknown/release/audio_recorder.wasm --out-dir ./recorder_output/ --target web
 }
 function run_recorder() {
-    simple-http-server -p 6969 -l 500000000 -u -- ./recorder_output/
+    cargo run --release --features server --bin recorder_server -- 0.0.0.0:6969 ./recorder_output/
 }
 
 function build_and_run(){
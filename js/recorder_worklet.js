@@ -0,0 +1,43 @@
+// Capture processor for the wasm recorder.
+// Render quanta (128 frames) are batched into blocks of `blockSize` frames and
+// posted back to the main thread through the node's message port.
+// Blocks are interleaved when more than one channel is captured.
+class RecorderProcessor extends AudioWorkletProcessor {
+    constructor(options) {
+        super();
+        const processorOptions = options.processorOptions || {};
+        this.blockSize = processorOptions.blockSize || 1024;
+        this.channels = processorOptions.channels || 1;
+        this.block = new Float32Array(this.blockSize * this.channels);
+        this.cursor = 0;
+        this.running = true;
+        this.port.onmessage = (e) => {
+            if (e.data === "stop") {
+                this.running = false;
+            }
+        };
+    }
+
+    process(inputs) {
+        const input = inputs[0];
+        if (input && input.length > 0) {
+            const frames = input[0].length;
+            for (let frame = 0; frame < frames; frame++) {
+                for (let channel = 0; channel < this.channels; channel++) {
+                    // a source with fewer channels than requested gets its last channel repeated
+                    const samples = input[Math.min(channel, input.length - 1)];
+                    this.block[this.cursor++] = samples[frame];
+                }
+                if (this.cursor >= this.block.length) {
+                    // transfer ownership of the block so no copy is made
+                    this.port.postMessage(this.block, [this.block.buffer]);
+                    this.block = new Float32Array(this.blockSize * this.channels);
+                    this.cursor = 0;
+                }
+            }
+        }
+        return this.running;
+    }
+}
+
+registerProcessor("recorder-processor", RecorderProcessor);
@@ -0,0 +1,348 @@
+// Converts and inspects adhoc recordings outside the browser
+// run it with `cargo run --features desktop --bin recorder_cli -- <command>`
+// - `decode <in.adhoc> [out.wav]`
+// - `encode <in.wav> [out.adhoc] [--level <n>]`
+// - `info <in.adhoc>`
+// - `trim <in.adhoc> [out.adhoc] [--max-gap <secs|off>] [--level <n>]`
+// - `batch <decode|encode|info> <directory> [--level <n>]`
+
+use audio_recorder::{
+    recorder::COMPRESSION_LEVEL,
+    recording::{transcode_from_wav, transcode_to_wav, trim_recording, RecordingInfo, TrimConfig},
+};
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter},
+    path::{Path, PathBuf},
+    process,
+};
+
+const USAGE: &str = "usage:
+    recorder_cli decode <in.adhoc> [out.wav]
+    recorder_cli encode <in.wav> [out.adhoc] [--level <n>]
+    recorder_cli info <in.adhoc>
+    recorder_cli trim <in.adhoc> [out.adhoc] [--max-gap <secs|off>] [--level <n>]
+    recorder_cli batch <decode|encode|info> <directory> [--level <n>]";
+
+#[derive(Clone, Debug, PartialEq)]
+enum Command {
+    Decode {
+        input: PathBuf,
+        output: PathBuf,
+    },
+    Encode {
+        input: PathBuf,
+        output: PathBuf,
+        level: u32,
+    },
+    Info {
+        input: PathBuf,
+    },
+    /// cuts the silence around the speech in a recording, see `TrimConfig`
+    Trim {
+        input: PathBuf,
+        output: PathBuf,
+        /// pauses are shortened to this, `None` keeps them
+        max_gap: Option<f32>,
+        level: u32,
+    },
+    /// runs `command` over every file in `directory` it applies to
+    Batch {
+        command: String,
+        directory: PathBuf,
+        level: u32,
+    },
+}
+
+/// # Description
+/// parses the arguments following the program name
+fn parse_args<I>(args: I) -> Result<Command, String>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut level = COMPRESSION_LEVEL;
+    let mut max_gap = TrimConfig::default().max_gap_in_secs;
+    let mut positional = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--level" || arg == "-l" {
+            level = args
+                .next()
+                .and_then(|level| level.parse().ok())
+                .ok_or("--level expects a number")?;
+        } else if arg == "--max-gap" || arg == "-g" {
+            max_gap = match args.next().as_deref() {
+                Some("off") => None,
+                Some(secs) => Some(
+                    secs.parse()
+                        .ok()
+                        .filter(|&secs: &f32| secs >= 0.0)
+                        .ok_or("--max-gap expects seconds or off")?,
+                ),
+                None => return Err("--max-gap expects seconds or off".to_string()),
+            };
+        } else {
+            positional.push(arg);
+        }
+    }
+
+    let positional = positional
+        .iter()
+        .map(|arg| arg.as_str())
+        .collect::<Vec<_>>();
+    match positional[..] {
+        ["decode", input] => Ok(Command::Decode {
+            input: input.into(),
+            output: Path::new(input).with_extension("wav"),
+        }),
+        ["decode", input, output] => Ok(Command::Decode {
+            input: input.into(),
+            output: output.into(),
+        }),
+        ["encode", input] => Ok(Command::Encode {
+            input: input.into(),
+            output: Path::new(input).with_extension("adhoc"),
+            level,
+        }),
+        ["encode", input, output] => Ok(Command::Encode {
+            input: input.into(),
+            output: output.into(),
+            level,
+        }),
+        ["info", input] => Ok(Command::Info {
+            input: input.into(),
+        }),
+        ["trim", input] => Ok(Command::Trim {
+            input: input.into(),
+            output: Path::new(input).with_extension("trimmed.adhoc"),
+            max_gap,
+            level,
+        }),
+        ["trim", input, output] => Ok(Command::Trim {
+            input: input.into(),
+            output: output.into(),
+            max_gap,
+            level,
+        }),
+        ["batch", command @ ("decode" | "encode" | "info"), directory] => Ok(Command::Batch {
+            command: command.to_string(),
+            directory: directory.into(),
+            level,
+        }),
+        [] => Err("missing command".to_string()),
+        _ => Err(format!("unexpected arguments: {}", positional.join(" "))),
+    }
+}
+
+/// # Description
+/// runs a single command
+/// ## Returns
+/// whatever should be printed
+fn run(command: &Command) -> io::Result<String> {
+    match command {
+        Command::Decode { input, output } => {
+            let frames = transcode_to_wav(
+                BufReader::new(File::open(input)?),
+                BufWriter::new(File::create(output)?),
+            )?;
+            Ok(format!(
+                "{} -> {} ({} frames)",
+                input.display(),
+                output.display(),
+                frames
+            ))
+        }
+        Command::Encode {
+            input,
+            output,
+            level,
+        } => {
+            let frames = transcode_from_wav(
+                BufReader::new(File::open(input)?),
+                BufWriter::new(File::create(output)?),
+                *level,
+            )?;
+            Ok(format!(
+                "{} -> {} ({} frames)",
+                input.display(),
+                output.display(),
+                frames
+            ))
+        }
+        Command::Info { input } => {
+            let bytes = fs::read(input)?;
+            let info = RecordingInfo::inspect(&bytes)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            Ok(describe(input, &info))
+        }
+        Command::Trim {
+            input,
+            output,
+            max_gap,
+            level,
+        } => {
+            let (before, after) = trim_recording(
+                BufReader::new(File::open(input)?),
+                BufWriter::new(File::create(output)?),
+                &TrimConfig::default().with_max_gap(*max_gap),
+                *level,
+            )?;
+            Ok(format!(
+                "{} -> {} ({} -> {} frames)",
+                input.display(),
+                output.display(),
+                before,
+                after
+            ))
+        }
+        Command::Batch {
+            command,
+            directory,
+            level,
+        } => batch(command, directory, *level),
+    }
+}
+
+fn describe(input: &Path, info: &RecordingInfo) -> String {
+    let bitrate = if info.duration_in_secs > 0.0 {
+        info.size as f64 * 8.0 / info.duration_in_secs / 1000.0
+    } else {
+        0.0
+    };
+    format!(
+        "{}\n  sample rate: {} Hz\n  channels:    {}\n  frames:      {}\n  duration:    {:.3} s\n  size:        {} bytes\n  bitrate:     {:.1} kbit/s",
+        input.display(),
+        info.sample_rate,
+        info.channels,
+        info.frames,
+        info.duration_in_secs,
+        info.size,
+        bitrate
+    )
+}
+
+/// # Description
+/// runs `command` over every `.adhoc` (or `.wav` when encoding) file directly inside `directory`
+/// ## Comments
+/// - a file that fails is reported and skipped, the batch fails once every file has been tried
+fn batch(command: &str, directory: &Path, level: u32) -> io::Result<String> {
+    let extension = if command == "encode" { "wav" } else { "adhoc" };
+    let mut inputs = fs::read_dir(directory)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && path.extension().map_or(false, |ext| ext == extension))
+        .collect::<Vec<_>>();
+    inputs.sort();
+
+    let mut failures = 0;
+    for input in inputs {
+        let name = input.display().to_string();
+        let single = match command {
+            "decode" => Command::Decode {
+                output: input.with_extension("wav"),
+                input,
+            },
+            "encode" => Command::Encode {
+                output: input.with_extension("adhoc"),
+                input,
+                level,
+            },
+            _ => Command::Info { input },
+        };
+        match run(&single) {
+            Ok(report) => println!("{}", report),
+            Err(err) => {
+                failures += 1;
+                eprintln!("{}: {}", name, err);
+            }
+        }
+    }
+
+    if failures > 0 {
+        Err(io::Error::other(format!("{} file(s) failed", failures)))
+    } else {
+        Ok(format!("done with {}", directory.display()))
+    }
+}
+
+fn main() {
+    let command = match parse_args(std::env::args().skip(1)) {
+        Ok(command) => command,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            process::exit(2);
+        }
+    };
+    match run(&command) {
+        Ok(report) => println!("{}", report),
+        Err(err) => {
+            eprintln!("error: {}", err);
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use adhoc_audio::{AdhocCodec, StreamInfo, Streamable};
+
+    fn args(line: &str) -> Result<Command, String> {
+        parse_args(line.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn outputs_default_to_the_input_with_a_new_extension() {
+        assert_eq!(
+            args("decode rec.adhoc"),
+            Ok(Command::Decode {
+                input: "rec.adhoc".into(),
+                output: "rec.wav".into()
+            })
+        );
+        assert_eq!(
+            args("encode --level 7 take.wav out.adhoc"),
+            Ok(Command::Encode {
+                input: "take.wav".into(),
+                output: "out.adhoc".into(),
+                level: 7
+            })
+        );
+        assert_eq!(
+            args("trim rec.adhoc -g off"),
+            Ok(Command::Trim {
+                input: "rec.adhoc".into(),
+                output: "rec.trimmed.adhoc".into(),
+                max_gap: None,
+                level: COMPRESSION_LEVEL
+            })
+        );
+        assert!(args("trim rec.adhoc --max-gap soon").is_err());
+        assert!(args("batch play ./recordings").is_err());
+        assert!(args("encode take.wav --level").is_err());
+        assert!(args("").is_err());
+    }
+
+    #[test]
+    fn batches_convert_every_recording_in_a_directory() {
+        let directory = std::env::temp_dir().join("recorder_cli_batch_test");
+        fs::remove_dir_all(&directory).ok();
+        fs::create_dir_all(&directory).unwrap();
+        for (k, name) in ["a.adhoc", "b.adhoc"].iter().enumerate() {
+            let mut codec = AdhocCodec::new().with_info(StreamInfo::new(8_000, 1));
+            codec.encode(&vec![0.25; 4_000 * (k + 1)]);
+            codec.save_to(File::create(directory.join(name)).unwrap());
+        }
+        fs::write(directory.join("notes.txt"), "not a recording").unwrap();
+
+        run(&args(&format!("batch decode {}", directory.display())).unwrap()).unwrap();
+        assert!(directory.join("a.wav").exists());
+        assert!(directory.join("b.wav").exists());
+        assert!(!directory.join("notes.wav").exists());
+
+        fs::remove_file(directory.join("a.adhoc")).unwrap();
+        fs::remove_file(directory.join("b.adhoc")).unwrap();
+        run(&args(&format!("batch encode {}", directory.display())).unwrap()).unwrap();
+        let info = RecordingInfo::inspect(&fs::read(directory.join("b.adhoc")).unwrap()).unwrap();
+        assert_eq!(info.frames, 8_000);
+    }
+}
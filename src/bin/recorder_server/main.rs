@@ -0,0 +1,670 @@
+// Serves the recorder demo and stores whatever it uploads
+// run it with `cargo run --features server --bin recorder_server -- [address] [root directory]`
+// - files under the root directory are served as is
+// - forms posted anywhere get their recordings validated and stored under `<root>/uploads`,
+//   every `<id>.adhoc` gets an `<id>.json` describing it and an `<id>.wav` to listen to
+//   a `<field>__recording_id` field holds the id of a recording uploaded in chunks for `<field>`
+// - `/upload` speaks the chunked upload protocol described in `audio_recorder::upload`
+
+mod multipart;
+
+use audio_recorder::{
+    recorder::COMPRESSION_LEVEL,
+    recording::{transcode_to_wav, RecordingInfo},
+    upload::{
+        is_valid_session_id, recording_id_field_owner, ChunkAssembler, UploadAck, UploadComplete,
+    },
+};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    fs,
+    io::{BufWriter, Read},
+    panic,
+    path::{Component, Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+const DEFAULT_ADDRESS: &str = "0.0.0.0:6969";
+const DEFAULT_ROOT: &str = "./recorder_output";
+const UPLOAD_ROUTE: &str = "/upload";
+/// default field recordings are uploaded under
+const RECORDING_FIELD: &str = "audio_recording";
+/// segments are a few seconds of compressed audio, anything this big is not a segment
+const MAX_CHUNK_SIZE: u64 = 16 * 1024 * 1024;
+const MAX_FORM_SIZE: u64 = 500_000_000;
+/// uploads nothing was sent for in this long were abandoned, their segments are dropped
+const SESSION_EXPIRY: Duration = Duration::from_secs(60 * 60);
+/// uploads held at once, new ones are turned away until some finish or expire
+const MAX_SESSIONS: usize = 256;
+
+/// # Description
+/// status code and json body of an answer
+type Reply = (u16, String);
+
+/// # Description
+/// what the sidecar next to a stored recording holds
+#[derive(Serialize)]
+struct StoredRecording {
+    id: String,
+    #[serde(flatten)]
+    info: RecordingInfo,
+}
+
+/// # Description
+/// the answer to a posted form, one entry per stored recording
+#[derive(Serialize)]
+struct FormReply {
+    recordings: Vec<FormRecording>,
+}
+
+#[derive(Serialize)]
+struct FormRecording {
+    field: String,
+    id: String,
+}
+
+/// # Description
+/// the segments of a chunked upload received so far
+struct UploadSession {
+    assembler: ChunkAssembler,
+    /// when the last segment came in
+    touched_at: Instant,
+}
+
+struct RecorderServer {
+    /// served as is, recordings are written to `<root>/uploads`
+    root: PathBuf,
+    sessions: HashMap<String, UploadSession>,
+    /// tells apart recordings stored in the same millisecond
+    stored: u32,
+}
+
+impl RecorderServer {
+    fn new(root: PathBuf) -> Self {
+        Self {
+            root,
+            sessions: HashMap::new(),
+            stored: 0,
+        }
+    }
+
+    fn uploads(&self) -> PathBuf {
+        self.root.join("uploads")
+    }
+
+    /// # Description
+    /// validates and stores a recording along with its json sidecar and a wav copy
+    /// ## Returns
+    /// the id the recording was stored under
+    fn store_recording(&mut self, bytes: &[u8]) -> Result<String, Reply> {
+        // a malformed upload may well panic the decoder, it must not take the server with it
+        let info = panic::catch_unwind(|| RecordingInfo::inspect(bytes))
+            .unwrap_or(Err("recording could not be decoded"))
+            .map_err(|err| error(422, err))?;
+
+        let directory = self.uploads();
+        fs::create_dir_all(&directory).map_err(|_| error(500, "upload directory unavailable"))?;
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|t| t.as_millis())
+            .unwrap_or(0);
+        let id = loop {
+            self.stored = self.stored.wrapping_add(1);
+            let id = format!("{}-{:04}", millis, self.stored % 10_000);
+            if !directory.join(format!("{}.adhoc", id)).exists() {
+                break id;
+            }
+        };
+
+        let sidecar = StoredRecording {
+            id: id.clone(),
+            info,
+        };
+        // everything is written under a temporary name first, so a failure leaves nothing behind
+        let files = ["adhoc", "json", "wav"].map(|extension| {
+            let file = directory.join(format!("{}.{}", id, extension));
+            (file.with_extension(format!("{}.part", extension)), file)
+        });
+        let [(adhoc, _), (json, _), (wav, _)] = &files;
+        let written = fs::write(adhoc, bytes)
+            .and_then(|_| {
+                fs::write(
+                    json,
+                    serde_json::to_string_pretty(&sidecar).unwrap_or_default(),
+                )
+            })
+            .map_err(|_| error(500, "recording could not be stored"))
+            .and_then(|_| {
+                fs::File::create(wav)
+                    .and_then(|wav| transcode_to_wav(bytes, BufWriter::new(wav)))
+                    .map_err(|_| error(500, "recording could not be transcoded"))
+            })
+            .and_then(|_| {
+                files
+                    .iter()
+                    .try_for_each(|(part, file)| fs::rename(part, file))
+                    .map_err(|_| error(500, "recording could not be stored"))
+            });
+        if let Err(reply) = written {
+            for (part, file) in &files {
+                fs::remove_file(part).ok();
+                fs::remove_file(file).ok();
+            }
+            return Err(reply);
+        }
+
+        println!(
+            "stored recording {} ({:.1}s)",
+            id, sidecar.info.duration_in_secs
+        );
+        Ok(id)
+    }
+
+    /// # Description
+    /// stores every recording in a posted form
+    /// ## Comments
+    /// - recorders upload under `audio_recording` unless they were given a `data-field-name`,
+    ///   so every file field is treated as a recording
+    /// - a recorder uploading in chunks has its recording stored once the upload finished, the
+    ///   form only carries the id it was stored under, in `<field>__recording_id`
+    /// - every other text field is the page's own and left alone
+    fn handle_form(&mut self, content_type: &str, body: &[u8]) -> Reply {
+        let boundary = match multipart::boundary(content_type) {
+            Some(boundary) => boundary,
+            None => return error(415, "expected multipart/form-data"),
+        };
+        let (files, fields): (Vec<_>, Vec<_>) = multipart::parse(body, boundary)
+            .into_iter()
+            .partition(|part| part.file_name.is_some());
+
+        let mut recordings = Vec::new();
+        for part in fields {
+            let field = match recording_id_field_owner(&part.name) {
+                Some(field) => field.to_string(),
+                None => continue,
+            };
+            let id = match std::str::from_utf8(part.data).map(str::trim) {
+                Ok(id) if is_recording_id(id) => id.to_string(),
+                _ => return error(422, &format!("{} is not a recording id", part.name)),
+            };
+            if !self.uploads().join(format!("{}.adhoc", id)).is_file() {
+                return error(422, &format!("no recording stored under {}", id));
+            }
+            recordings.push(FormRecording { field, id });
+        }
+        for part in files {
+            match self.store_recording(part.data) {
+                Ok(id) => recordings.push(FormRecording {
+                    field: part.name,
+                    id,
+                }),
+                Err(reply) => return reply,
+            }
+        }
+        if recordings.is_empty() {
+            return error(400, &format!("missing the {} field", RECORDING_FIELD));
+        }
+        json(200, &FormReply { recordings })
+    }
+
+    /// # Description
+    /// routes a request of the chunked upload protocol
+    /// ## Parameters
+    /// - `url` is the request path including its query
+    fn handle_chunks(&mut self, method: &Method, url: &str, body: Vec<u8>) -> Reply {
+        let (path, query) = url.split_once('?').unwrap_or((url, ""));
+        let route = match path.strip_prefix(UPLOAD_ROUTE) {
+            Some(route) => route.trim_matches('/'),
+            None => return error(404, "not found"),
+        };
+        let segments = route.split('/').collect::<Vec<_>>();
+        let session = segments[0];
+        if !is_valid_session_id(session) {
+            return error(400, "bad session id");
+        }
+
+        match (method, &segments[1..]) {
+            (Method::Get, []) => self.acknowledge(session),
+            (Method::Put, [sequence]) => match sequence.parse::<u32>() {
+                Ok(sequence) => self.receive(session, sequence, body, Instant::now()),
+                Err(_) => error(400, "bad sequence number"),
+            },
+            (Method::Post, ["finish"]) => {
+                let chunks = query
+                    .split('&')
+                    .find_map(|pair| pair.strip_prefix("chunks="))
+                    .and_then(|chunks| chunks.parse::<u32>().ok());
+                match chunks {
+                    Some(chunks) => self.finish(session, chunks),
+                    None => error(400, "missing chunk count"),
+                }
+            }
+            _ => error(405, "method not allowed"),
+        }
+    }
+
+    /// # Description
+    /// stores a segment of an upload, starting the upload when it's the first one
+    /// ## Comments
+    /// - abandoned uploads are dropped first, see `SESSION_EXPIRY`, and no more than
+    ///   `MAX_SESSIONS` are held at once
+    fn receive(&mut self, session: &str, sequence: u32, body: Vec<u8>, now: Instant) -> Reply {
+        self.sessions
+            .retain(|_, upload| now.duration_since(upload.touched_at) < SESSION_EXPIRY);
+        if !self.sessions.contains_key(session) && self.sessions.len() >= MAX_SESSIONS {
+            return error(503, "too many uploads in progress");
+        }
+        let upload = self
+            .sessions
+            .entry(session.to_string())
+            .or_insert_with(|| UploadSession {
+                assembler: ChunkAssembler::new().with_compression_level(COMPRESSION_LEVEL),
+                touched_at: now,
+            });
+        upload.assembler.receive(sequence, body);
+        upload.touched_at = now;
+        self.acknowledge(session)
+    }
+
+    fn acknowledge(&self, session: &str) -> Reply {
+        let next_sequence = self
+            .sessions
+            .get(session)
+            .map(|upload| upload.assembler.next_sequence())
+            .unwrap_or(0);
+        json(
+            200,
+            &UploadAck {
+                session: session.to_string(),
+                next_sequence,
+            },
+        )
+    }
+
+    fn finish(&mut self, session: &str, chunks: u32) -> Reply {
+        let assembler = match self.sessions.get(session) {
+            Some(upload) => &upload.assembler,
+            None => return error(404, "unknown session"),
+        };
+        // segments come straight off the network, a malformed one may well panic the decoder
+        let recording = match panic::catch_unwind(|| assembler.assemble(chunks)) {
+            Ok(Ok(recording)) => recording,
+            Ok(Err(err)) => return error(409, &err.to_string()),
+            Err(_) => {
+                self.sessions.remove(session);
+                return error(422, "segments could not be decoded");
+            }
+        };
+        let mut bytes = Vec::new();
+        if recording.save_to(&mut bytes).is_none() {
+            return error(500, "recording could not be reassembled");
+        }
+
+        match self.store_recording(&bytes) {
+            Ok(id) => {
+                self.sessions.remove(session);
+                json(
+                    200,
+                    &UploadComplete {
+                        session: session.to_string(),
+                        id,
+                    },
+                )
+            }
+            Err(reply) => reply,
+        }
+    }
+
+    /// # Description
+    /// maps a request path onto a file under the root directory
+    /// ## Comments
+    /// - anything trying to climb out of the root directory is refused
+    fn static_file(&self, url: &str) -> Option<PathBuf> {
+        let path = url.split(['?', '#']).next().unwrap_or("");
+        let relative = Path::new(path.trim_start_matches('/'));
+        if relative
+            .components()
+            .any(|component| !matches!(component, Component::Normal(_)))
+        {
+            return None;
+        }
+        let file = self.root.join(relative);
+        if file.is_dir() {
+            Some(file.join("index.html"))
+        } else {
+            Some(file)
+        }
+    }
+}
+
+/// # Description
+/// whether `value` is spelled like the ids `store_recording` hands out, `<millis>-<nnnn>`
+/// ## Comments
+/// - ids end up in file names, anything else must not get near the upload directory
+fn is_recording_id(value: &str) -> bool {
+    match value.split_once('-') {
+        Some((millis, count)) => {
+            !millis.is_empty()
+                && millis.chars().all(|c| c.is_ascii_digit())
+                && count.len() == 4
+                && count.chars().all(|c| c.is_ascii_digit())
+        }
+        None => false,
+    }
+}
+
+fn json<T: Serialize>(status: u16, body: &T) -> Reply {
+    (status, serde_json::to_string(body).unwrap_or_default())
+}
+
+fn error(status: u16, message: &str) -> Reply {
+    json(status, &serde_json::json!({ "error": message }))
+}
+
+fn content_type(file: &Path) -> &'static str {
+    match file.extension().and_then(|ext| ext.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("js") => "application/javascript",
+        Some("wasm") => "application/wasm",
+        Some("css") => "text/css",
+        Some("json") => "application/json",
+        Some("wav") => "audio/wav",
+        _ => "application/octet-stream",
+    }
+}
+
+fn header(field: &str, value: &str) -> Header {
+    Header::from_bytes(field.as_bytes(), value.as_bytes()).expect("invalid header")
+}
+
+fn respond(mut request: Request, server: &mut RecorderServer) {
+    let method = request.method().clone();
+    let url = request.url().to_string();
+    let is_chunk = url.starts_with(UPLOAD_ROUTE);
+
+    if !is_chunk && matches!(method, Method::Get | Method::Head) {
+        let file = server
+            .static_file(&url)
+            .and_then(|path| fs::File::open(&path).ok().map(|file| (path, file)));
+        let result = match file {
+            Some((path, file)) => request.respond(
+                Response::from_file(file).with_header(header("Content-Type", content_type(&path))),
+            ),
+            None => request.respond(Response::from_string("not found").with_status_code(404)),
+        };
+        if let Err(err) = result {
+            eprintln!("failed to respond: {}", err);
+        }
+        return;
+    }
+
+    let limit = if is_chunk {
+        MAX_CHUNK_SIZE
+    } else {
+        MAX_FORM_SIZE
+    };
+    let content_type = request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("Content-Type"))
+        .map(|h| h.value.as_str().to_string())
+        .unwrap_or_default();
+    let mut body = Vec::new();
+    let read = request.as_reader().take(limit + 1).read_to_end(&mut body);
+
+    let (status, reply) = match read {
+        Ok(_) if body.len() as u64 > limit => error(413, "body too large"),
+        Ok(_) if is_chunk => server.handle_chunks(&method, &url, body),
+        Ok(_) if method == Method::Post => server.handle_form(&content_type, &body),
+        Ok(_) => error(405, "method not allowed"),
+        Err(_) => error(400, "body could not be read"),
+    };
+    let response = Response::from_string(reply)
+        .with_status_code(status)
+        .with_header(header("Content-Type", "application/json"));
+    if let Err(err) = request.respond(response) {
+        eprintln!("failed to respond: {}", err);
+    }
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let address = args.next().unwrap_or_else(|| DEFAULT_ADDRESS.to_string());
+    let root = PathBuf::from(args.next().unwrap_or_else(|| DEFAULT_ROOT.to_string()));
+
+    let http = Server::http(&address).expect("failed to bind server");
+    println!("serving {} on http://{}", root.display(), address);
+
+    let mut server = RecorderServer::new(root);
+    for request in http.incoming_requests() {
+        respond(request, &mut server);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use adhoc_audio::{AdhocCodec, StreamInfo, Streamable};
+    use audio_recorder::upload::{chunk_url, finish_url, status_url, SegmentEncoder};
+
+    fn test_server(name: &str) -> RecorderServer {
+        let root = std::env::temp_dir().join(name);
+        fs::remove_dir_all(&root).ok();
+        RecorderServer::new(root)
+    }
+
+    fn tone_recording(frames: usize) -> Vec<u8> {
+        let mut codec = AdhocCodec::new().with_info(StreamInfo::new(8_000, 1));
+        let tone = (0..frames)
+            .map(|k| (k as f32 * 0.05).sin() * 0.5)
+            .collect::<Vec<_>>();
+        codec.encode(&tone);
+        let mut bytes = Vec::new();
+        codec.save_to(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn posted_recordings_are_validated_and_stored_with_a_sidecar() {
+        let mut server = test_server("recorder_server_form_test");
+        let recording = tone_recording(8_000);
+
+        let mut body = b"--b0undary\r\nContent-Disposition: form-data; name=\"audio_recording\"; filename=\"rec.adhoc\"\r\n\r\n".to_vec();
+        body.extend_from_slice(&recording);
+        body.extend_from_slice(b"\r\n--b0undary--\r\n");
+        let (status, reply) = server.handle_form("multipart/form-data; boundary=b0undary", &body);
+        assert_eq!(status, 200, "{}", reply);
+
+        let reply: serde_json::Value = serde_json::from_str(&reply).unwrap();
+        let id = reply["recordings"][0]["id"].as_str().unwrap();
+        assert_eq!(reply["recordings"][0]["field"], RECORDING_FIELD);
+        let stored = fs::read(server.uploads().join(format!("{}.adhoc", id))).unwrap();
+        assert_eq!(stored, recording);
+        let sidecar: serde_json::Value = serde_json::from_slice(
+            &fs::read(server.uploads().join(format!("{}.json", id))).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(sidecar["id"], id);
+        assert_eq!(sidecar["sample_rate"], 8_000);
+        assert_eq!(sidecar["channels"], 1);
+        assert_eq!(sidecar["duration_in_secs"], 1.0);
+        assert_eq!(sidecar["size"], recording.len());
+        let wav = fs::read(server.uploads().join(format!("{}.wav", id))).unwrap();
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(wav.len(), 44 + 8_000 * 2);
+
+        let garbage = b"--b0undary\r\nContent-Disposition: form-data; name=\"audio_recording\"; filename=\"rec.adhoc\"\r\n\r\nnot audio\r\n--b0undary--\r\n";
+        let (status, _) = server.handle_form("multipart/form-data; boundary=b0undary", garbage);
+        assert_eq!(status, 422);
+    }
+
+    #[test]
+    fn forms_of_chunked_recorders_carry_the_id_of_the_stored_recording() {
+        let mut server = test_server("recorder_server_chunked_form_test");
+        let id = server.store_recording(&tone_recording(8_000)).unwrap();
+
+        let form = |id: &str| {
+            format!(
+                "--b0undary\r\nContent-Disposition: form-data; name=\"zip\"\r\n\r\n12345-6789\r\n\
+                 --b0undary\r\nContent-Disposition: form-data; name=\"audio_recording__recording_id\"\r\n\r\n{}\r\n\
+                 --b0undary--\r\n",
+                id
+            )
+        };
+        let (status, reply) = server.handle_form(
+            "multipart/form-data; boundary=b0undary",
+            form(&id).as_bytes(),
+        );
+        assert_eq!(status, 200, "{}", reply);
+        let reply: serde_json::Value = serde_json::from_str(&reply).unwrap();
+        assert_eq!(reply["recordings"][0]["field"], RECORDING_FIELD);
+        assert_eq!(reply["recordings"][0]["id"], id.as_str());
+        assert_eq!(reply["recordings"].as_array().unwrap().len(), 1);
+
+        for unknown in ["1-0001", "../secret"] {
+            let (status, _) = server.handle_form(
+                "multipart/form-data; boundary=b0undary",
+                form(unknown).as_bytes(),
+            );
+            assert_eq!(status, 422);
+        }
+    }
+
+    #[test]
+    fn text_fields_shaped_like_ids_are_left_alone() {
+        let mut server = test_server("recorder_server_zip_form_test");
+        let zip_only = "--b0undary\r\nContent-Disposition: form-data; name=\"zip\"\r\n\r\n12345-6789\r\n--b0undary--\r\n";
+        let (status, reply) = server.handle_form(
+            "multipart/form-data; boundary=b0undary",
+            zip_only.as_bytes(),
+        );
+        assert_eq!(status, 400, "{}", reply);
+
+        let mut body = b"--b0undary\r\nContent-Disposition: form-data; name=\"zip\"\r\n\r\n12345-6789\r\n--b0undary\r\nContent-Disposition: form-data; name=\"audio_recording\"; filename=\"rec.adhoc\"\r\n\r\n".to_vec();
+        body.extend_from_slice(&tone_recording(8_000));
+        body.extend_from_slice(b"\r\n--b0undary--\r\n");
+        let (status, reply) = server.handle_form("multipart/form-data; boundary=b0undary", &body);
+        assert_eq!(status, 200, "{}", reply);
+        let reply: serde_json::Value = serde_json::from_str(&reply).unwrap();
+        assert_eq!(reply["recordings"].as_array().unwrap().len(), 1);
+        assert_eq!(reply["recordings"][0]["field"], RECORDING_FIELD);
+    }
+
+    #[test]
+    fn failed_stores_leave_no_files_behind() {
+        let mut server = test_server("recorder_server_failed_store_test");
+        fs::create_dir_all(server.uploads()).unwrap();
+        // a directory in the way of the wav copy fails the store after the recording was written
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis();
+        for offset in 0..1_000 {
+            let blocker = server
+                .uploads()
+                .join(format!("{}-{:04}.wav.part", millis + offset, 1));
+            fs::create_dir_all(blocker).unwrap();
+        }
+
+        let (status, _) = server.store_recording(&tone_recording(8_000)).unwrap_err();
+        assert_eq!(status, 500);
+        let leftovers = fs::read_dir(server.uploads())
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().is_file())
+            .count();
+        assert_eq!(leftovers, 0);
+    }
+
+    #[test]
+    fn interrupted_upload_resumes_and_reassembles() {
+        let mut server = test_server("recorder_server_chunk_test");
+        let session = "resume-test";
+
+        let info = StreamInfo::new(8_000, 1);
+        let mut segments = SegmentEncoder::new(info, 4).with_segment_duration(0.5);
+        let tone = (0..16_000)
+            .map(|k| (k as f32 * 0.05).sin() * 0.5)
+            .collect::<Vec<_>>();
+        let mut chunks = tone
+            .chunks(1024)
+            .filter_map(|block| segments.encode(block))
+            .collect::<Vec<_>>();
+        chunks.extend(segments.flush());
+
+        // the connection drops after the second segment
+        for chunk in &chunks[0..2] {
+            let url = chunk_url(UPLOAD_ROUTE, session, chunk.sequence);
+            let (status, _) = server.handle_chunks(&Method::Put, &url, chunk.bytes.clone());
+            assert_eq!(status, 200);
+        }
+        let (status, reply) =
+            server.handle_chunks(&Method::Get, &status_url(UPLOAD_ROUTE, session), vec![]);
+        assert_eq!(status, 200);
+        let ack: UploadAck = serde_json::from_str(&reply).unwrap();
+        assert_eq!(ack.next_sequence, 2);
+
+        let url = finish_url(UPLOAD_ROUTE, session, segments.segments());
+        assert_eq!(server.handle_chunks(&Method::Post, &url, vec![]).0, 409);
+
+        for chunk in &chunks[ack.next_sequence as usize..] {
+            let url = chunk_url(UPLOAD_ROUTE, session, chunk.sequence);
+            let (status, _) = server.handle_chunks(&Method::Put, &url, chunk.bytes.clone());
+            assert_eq!(status, 200);
+        }
+        let (status, reply) = server.handle_chunks(&Method::Post, &url, vec![]);
+        assert_eq!(status, 200);
+        let complete: UploadComplete = serde_json::from_str(&reply).unwrap();
+
+        let stored = fs::read(server.uploads().join(format!("{}.adhoc", complete.id))).unwrap();
+        let info = RecordingInfo::inspect(&stored).expect("stored recording is invalid");
+        assert_eq!(info.frames, tone.len() as u64);
+    }
+
+    #[test]
+    fn malformed_segments_are_turned_down_without_taking_the_server_down() {
+        let mut server = test_server("recorder_server_malformed_chunk_test");
+        let mut segment = tone_recording(4_000);
+        // the compression level, shifting by it overflows
+        segment[0] = 0xff;
+        let url = chunk_url(UPLOAD_ROUTE, "malformed", 0);
+        assert_eq!(server.handle_chunks(&Method::Put, &url, segment).0, 200);
+
+        let url = finish_url(UPLOAD_ROUTE, "malformed", 1);
+        assert_eq!(server.handle_chunks(&Method::Post, &url, vec![]).0, 422);
+        assert!(server.sessions.is_empty());
+    }
+
+    #[test]
+    fn abandoned_uploads_expire_and_their_number_is_limited() {
+        let mut server = test_server("recorder_server_session_limit_test");
+        let start = Instant::now();
+        for k in 0..MAX_SESSIONS {
+            let (status, _) = server.receive(&format!("s{}", k), 0, vec![], start);
+            assert_eq!(status, 200);
+        }
+        assert_eq!(server.receive("one-too-many", 0, vec![], start).0, 503);
+        // uploads already going on carry on
+        assert_eq!(server.receive("s0", 1, vec![], start).0, 200);
+
+        let later = start + SESSION_EXPIRY;
+        assert_eq!(server.receive("one-too-many", 0, vec![], later).0, 200);
+        assert_eq!(server.sessions.len(), 1);
+    }
+
+    #[test]
+    fn requests_never_leave_the_root_directory() {
+        let mut server = test_server("recorder_server_path_test");
+        let (status, _) =
+            server.handle_chunks(&Method::Post, "/upload/..%2F/finish?chunks=1", vec![]);
+        assert_eq!(status, 400);
+        assert!(server.static_file("/../secret").is_none());
+        assert!(server.static_file("/pkg/../../secret").is_none());
+        assert_eq!(
+            server.static_file("/audio_recorder.js?v=2"),
+            Some(server.root.join("audio_recorder.js"))
+        );
+    }
+}
@@ -0,0 +1,115 @@
+/// # Description
+/// a single field of a `multipart/form-data` body
+pub struct Part<'a> {
+    pub name: String,
+    /// only set for file fields
+    pub file_name: Option<String>,
+    pub data: &'a [u8],
+}
+
+/// # Description
+/// pulls the boundary out of a `multipart/form-data` content type
+pub fn boundary(content_type: &str) -> Option<&str> {
+    let (mime, params) = content_type.split_once(';')?;
+    if !mime.trim().eq_ignore_ascii_case("multipart/form-data") {
+        return None;
+    }
+    params
+        .split(';')
+        .filter_map(|param| param.trim().split_once('='))
+        .find(|(key, _)| key.eq_ignore_ascii_case("boundary"))
+        .map(|(_, value)| value.trim_matches('"'))
+        .filter(|value| !value.is_empty())
+}
+
+/// # Description
+/// splits a `multipart/form-data` body into its fields
+/// ## Comments
+/// - parts without a `content-disposition` name are skipped
+pub fn parse<'a>(body: &'a [u8], boundary: &str) -> Vec<Part<'a>> {
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let mut parts = Vec::new();
+    let mut rest = match find(body, &delimiter) {
+        Some(start) => &body[start + delimiter.len()..],
+        None => return parts,
+    };
+
+    // every part sits between `--boundary\r\n` and `\r\n--boundary`, the last delimiter ends in `--`
+    while !rest.starts_with(b"--") {
+        let part = match rest.strip_prefix(b"\r\n") {
+            Some(part) => part,
+            None => break,
+        };
+        let end = match find(part, &delimiter) {
+            Some(end) => end,
+            None => break,
+        };
+        let content = part[..end].strip_suffix(b"\r\n").unwrap_or(&part[..end]);
+        rest = &part[end + delimiter.len()..];
+
+        if let Some(header_end) = find(content, b"\r\n\r\n") {
+            let headers = String::from_utf8_lossy(&content[..header_end]);
+            let data = &content[header_end + 4..];
+            if let Some((name, file_name)) = content_disposition(&headers) {
+                parts.push(Part {
+                    name,
+                    file_name,
+                    data,
+                });
+            }
+        }
+    }
+    parts
+}
+
+/// # Description
+/// reads the field name and file name off a part's `content-disposition` header
+fn content_disposition(headers: &str) -> Option<(String, Option<String>)> {
+    let disposition = headers.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        Some(value).filter(|_| key.trim().eq_ignore_ascii_case("content-disposition"))
+    })?;
+    let param = |wanted: &str| {
+        disposition
+            .split(';')
+            .filter_map(|param| param.trim().split_once('='))
+            .find(|(key, _)| key.eq_ignore_ascii_case(wanted))
+            .map(|(_, value)| value.trim_matches('"').to_string())
+    };
+    Some((param("name")?, param("filename")))
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn form_fields_and_files_are_split_apart() {
+        let content_type = "multipart/form-data; boundary=----x42";
+        let body = b"------x42\r\n\
+            Content-Disposition: form-data; name=\"comment\"\r\n\r\n\
+            hello\r\n\
+            ------x42\r\n\
+            Content-Disposition: form-data; name=\"audio_recording\"; filename=\"rec.adhoc\"\r\n\
+            Content-Type: application/octet-stream\r\n\r\n\
+            \x00\x01\r\n\x02\r\n\
+            ------x42--\r\n";
+
+        let parts = parse(body, boundary(content_type).unwrap());
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].name, "comment");
+        assert_eq!(parts[0].file_name, None);
+        assert_eq!(parts[0].data, b"hello");
+        assert_eq!(parts[1].name, "audio_recording");
+        assert_eq!(parts[1].file_name.as_deref(), Some("rec.adhoc"));
+        assert_eq!(parts[1].data, b"\x00\x01\r\n\x02");
+
+        assert_eq!(boundary("application/json"), None);
+    }
+}
@@ -0,0 +1,690 @@
+use crate::math::{self, Resampler, VadConfig, VoiceActivityDetector};
+use adhoc_audio::{AdhocCodec, StreamInfo, Streamable};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+mod native;
+mod web;
+
+pub use native::*;
+pub use web::*;
+
+/// # Description
+/// Anything that produces blocks of PCM for the recorder to encode
+/// ## Comments
+/// - samples are INTERLEAVED IEE754 values ranging from -1.0 to 1.0, same as `Streamable`
+/// - `next_block` returning `None` means no complete block is available (yet)
+pub trait CaptureSource {
+    /// number of frames in a block, a frame is one sample per channel
+    fn block_size(&self) -> usize;
+
+    /// sample rate in Hz
+    fn sample_rate(&self) -> u32;
+
+    fn channels(&self) -> usize;
+
+    /// # Description
+    /// writes the next block into `block` and returns the number of samples written
+    fn next_block(&mut self, block: &mut [f32]) -> Option<usize>;
+
+    /// # Description
+    /// the `StreamInfo` a codec needs to encode this source
+    fn info(&self) -> StreamInfo {
+        StreamInfo::new(self.sample_rate(), self.channels() as u32)
+    }
+}
+
+/// # Description
+/// drains every block `source` has available into `codec`
+/// ## Parameters
+/// - `on_block` gets called with every block before it is encoded, it may modify the block
+/// - `on_block` returns `false` when the block should be dropped instead of encoded
+/// ## Returns
+/// Number of samples encoded
+pub fn record<S, CB>(source: &mut S, codec: &mut AdhocCodec, mut on_block: CB) -> usize
+where
+    S: CaptureSource + ?Sized,
+    CB: FnMut(&mut [f32]) -> bool,
+{
+    let mut block = vec![0.0; source.block_size() * source.channels()];
+    let mut samples_encoded = 0;
+    while let Some(samples_read) = source.next_block(&mut block) {
+        if on_block(&mut block[0..samples_read]) {
+            codec.encode(&block[0..samples_read]);
+            samples_encoded += samples_read;
+        }
+    }
+    samples_encoded
+}
+
+/// # Description
+/// whether a recording is currently taking in audio
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum RecordStatus {
+    Recording,
+    /// the capture is still running but its audio is dropped
+    Paused,
+    /// the capture has been torn down, what was recorded so far is kept
+    Stopped,
+}
+
+/// # Description
+/// Decides which blocks of a pausable recording get encoded
+/// ## Comments
+/// - the first block after a pause is faded out and the first block after a resume is faded in
+/// - this way takes are spliced together without gaps or clicks
+pub struct RecordGate {
+    channels: usize,
+    open: bool,
+}
+
+impl RecordGate {
+    pub fn new(channels: usize) -> Self {
+        Self {
+            channels,
+            open: false,
+        }
+    }
+
+    /// # Description
+    /// shapes `block` for `status`
+    /// ## Returns
+    /// `true` if the block should be encoded
+    pub fn apply(&mut self, status: RecordStatus, block: &mut [f32]) -> bool {
+        match (status, self.open) {
+            (RecordStatus::Recording, true) => true,
+            (RecordStatus::Recording, false) => {
+                math::fade(block, self.channels, 0.0, 1.0);
+                self.open = true;
+                true
+            }
+            (RecordStatus::Paused | RecordStatus::Stopped, true) => {
+                math::fade(block, self.channels, 1.0, 0.0);
+                self.open = false;
+                true
+            }
+            (RecordStatus::Paused | RecordStatus::Stopped, false) => false,
+        }
+    }
+
+    /// # Description
+    /// whether the last block made it into the recording
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// # Description
+    /// closes the gate without fading anything out, for when there's nothing left to fade
+    /// ## Comments
+    /// - the next block that gets recorded is still faded in
+    pub fn close(&mut self) {
+        self.open = false;
+    }
+}
+
+/// # Description
+/// how a voice activated recording behaves
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct VoiceActivation {
+    pub vad: VadConfig,
+    /// recording stops once nobody spoke for this long, `None` keeps it going until it's stopped
+    pub stop_after_silence_in_secs: Option<f32>,
+    /// silence kept before speech starts and after it ends, so words don't get clipped
+    pub padding_in_secs: f32,
+}
+
+impl Default for VoiceActivation {
+    fn default() -> Self {
+        Self {
+            vad: VadConfig::default(),
+            stop_after_silence_in_secs: Some(2.0),
+            padding_in_secs: 0.3,
+        }
+    }
+}
+
+impl VoiceActivation {
+    pub fn with_stop_after_silence(mut self, secs: Option<f32>) -> Self {
+        self.stop_after_silence_in_secs = secs;
+        self
+    }
+}
+
+/// # Description
+/// what a `VoiceGate` noticed in the blocks it was fed
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum VoiceEvent {
+    SpeechStarted,
+    SpeechEnded,
+    /// nobody spoke for `stop_after_silence_in_secs`, the recording should stop
+    SilenceTimeout,
+}
+
+/// # Description
+/// Keeps the silence around speech out of a recording
+/// ## Comments
+/// - blocks are held back until it's known whether speech follows them, nothing is let through
+///   before speech starts but the last `padding_in_secs`
+/// - silence after speech is let through for `padding_in_secs`, the rest is held back and only
+///   let through when speech picks up again, so pauses between words are kept but trailing
+///   silence isn't
+/// - blocks that make it through are queued in `released`
+pub struct VoiceGate {
+    activation: VoiceActivation,
+    detector: VoiceActivityDetector,
+    held: VecDeque<Vec<f32>>,
+    held_samples: usize,
+    /// samples of silence since speech last ended
+    silence: usize,
+    heard_speech: bool,
+    timed_out: bool,
+    released: BlockBuffer,
+}
+
+impl VoiceGate {
+    /// silence held back after speech is let through past this, to bound memory use
+    const MAX_HELD_IN_SECS: f32 = 30.0;
+
+    pub fn new(
+        activation: VoiceActivation,
+        block_size: usize,
+        sample_rate: u32,
+        channels: usize,
+    ) -> Self {
+        Self {
+            activation,
+            detector: VoiceActivityDetector::new(activation.vad, sample_rate, channels),
+            held: VecDeque::new(),
+            held_samples: 0,
+            silence: 0,
+            heard_speech: false,
+            timed_out: false,
+            released: BlockBuffer::new(block_size, sample_rate, channels),
+        }
+    }
+
+    pub fn activation(&self) -> VoiceActivation {
+        self.activation
+    }
+
+    /// # Description
+    /// runs every block `source` has available through the gate
+    /// ## Returns
+    /// the peak amplitude of those blocks and the latest event they caused
+    pub fn feed<S>(&mut self, source: &mut S) -> (Option<f32>, Option<VoiceEvent>)
+    where
+        S: CaptureSource + ?Sized,
+    {
+        let mut block = vec![0.0; source.block_size() * source.channels()];
+        let mut peak = None;
+        let mut event = None;
+        while let Some(samples_read) = source.next_block(&mut block) {
+            let block = &block[0..samples_read];
+            peak = peak
+                .into_iter()
+                .chain(math::peak_amplitude(block))
+                .reduce(f32::max);
+            event = self.push(block).or(event);
+        }
+        (peak, event)
+    }
+
+    /// # Description
+    /// blocks that made it through, waiting to be encoded
+    pub fn released(&mut self) -> &mut BlockBuffer {
+        &mut self.released
+    }
+
+    /// # Description
+    /// drops whatever is held back, the next recording waits for speech again
+    pub fn reset(&mut self) {
+        self.detector.reset();
+        self.held.clear();
+        self.held_samples = 0;
+        self.silence = 0;
+        self.heard_speech = false;
+        self.timed_out = false;
+    }
+
+    fn push(&mut self, block: &[f32]) -> Option<VoiceEvent> {
+        let was_speaking = self.detector.speaking();
+        let speaking = self.detector.process(block);
+        if speaking {
+            self.heard_speech = true;
+            self.silence = 0;
+            self.timed_out = false;
+            while let Some(held) = self.held.pop_front() {
+                self.released.push(&held);
+            }
+            self.held_samples = 0;
+            self.released.push(block);
+            return (!was_speaking).then_some(VoiceEvent::SpeechStarted);
+        }
+
+        let padding = self.samples_in(self.activation.padding_in_secs);
+        self.silence += block.len();
+        if self.heard_speech && self.silence <= padding {
+            self.released.push(block);
+        } else {
+            self.held.push_back(block.to_vec());
+            self.held_samples += block.len();
+        }
+        // before speech only the padding is worth holding on to
+        let max_held = if self.heard_speech {
+            self.samples_in(Self::MAX_HELD_IN_SECS)
+        } else {
+            padding
+        };
+        while self.held_samples > max_held {
+            let oldest = self.held.pop_front().unwrap_or_default();
+            self.held_samples -= oldest.len();
+            if self.heard_speech {
+                self.released.push(&oldest);
+            }
+        }
+
+        let timeout = self
+            .activation
+            .stop_after_silence_in_secs
+            .map(|secs| self.samples_in(secs));
+        if self.heard_speech && !self.timed_out && timeout.is_some_and(|t| self.silence >= t) {
+            self.timed_out = true;
+            self.held.clear();
+            self.held_samples = 0;
+            Some(VoiceEvent::SilenceTimeout)
+        } else {
+            was_speaking.then_some(VoiceEvent::SpeechEnded)
+        }
+    }
+
+    /// interleaved samples in `secs` of audio
+    fn samples_in(&self, secs: f32) -> usize {
+        (secs * self.released.sample_rate() as f32) as usize * self.released.channels()
+    }
+}
+
+/// # Description
+/// A `CaptureSource` over samples that were pushed into it, used to bridge
+/// callback-driven apis (like webaudio) into pull-based ones
+pub struct BlockBuffer {
+    pending: VecDeque<f32>,
+    block_size: usize,
+    sample_rate: u32,
+    channels: usize,
+    /// one resampler per channel, empty when no resampling is needed
+    resamplers: Vec<Resampler>,
+    resampled: Vec<Vec<f32>>,
+    interleaved: Vec<f32>,
+    /// set by `flush`, the last block may come out short
+    flushed: bool,
+}
+
+impl BlockBuffer {
+    pub fn new(block_size: usize, sample_rate: u32, channels: usize) -> Self {
+        Self {
+            pending: VecDeque::new(),
+            block_size,
+            sample_rate,
+            channels,
+            resamplers: Vec::new(),
+            resampled: Vec::new(),
+            interleaved: Vec::new(),
+            flushed: false,
+        }
+    }
+
+    /// # Description
+    /// resamples everything pushed into the buffer to `sample_rate`
+    pub fn with_sample_rate(mut self, sample_rate: u32) -> Self {
+        if sample_rate != self.sample_rate {
+            self.resamplers = (0..self.channels)
+                .map(|_| Resampler::new(self.sample_rate, sample_rate))
+                .collect();
+            self.sample_rate = sample_rate;
+        }
+        self
+    }
+
+    /// # Description
+    /// queues interleaved `samples`
+    pub fn push(&mut self, samples: &[f32]) {
+        if self.resamplers.is_empty() {
+            self.pending.extend(samples.iter());
+            return;
+        }
+
+        // every channel is resampled on its own, all resamplers advance in lockstep
+        // so the channels come out with equal lengths
+        let channels = math::deinterleave(samples, self.channels);
+        self.resampled.resize(self.channels, Vec::new());
+        for ((resampler, input), output) in self
+            .resamplers
+            .iter_mut()
+            .zip(channels.iter())
+            .zip(self.resampled.iter_mut())
+        {
+            output.clear();
+            resampler.process(input, output);
+        }
+
+        self.interleaved.clear();
+        math::interleave(&self.resampled, &mut self.interleaved);
+        self.pending.extend(self.interleaved.iter());
+    }
+
+    /// # Description
+    /// lets everything out, for when nothing more is coming
+    /// ## Comments
+    /// - the samples the resamplers still hold back are queued, and until the buffer runs dry
+    ///   `next_block` hands out short blocks as well
+    /// - once it ran dry only complete blocks are handed out again
+    pub fn flush(&mut self) {
+        if !self.resamplers.is_empty() {
+            self.resampled.resize(self.channels, Vec::new());
+            for (resampler, output) in self.resamplers.iter_mut().zip(self.resampled.iter_mut()) {
+                output.clear();
+                resampler.flush(output);
+            }
+            self.interleaved.clear();
+            math::interleave(&self.resampled, &mut self.interleaved);
+            self.pending.extend(self.interleaved.iter());
+        }
+        self.flushed = true;
+    }
+
+    /// # Description
+    /// drops at most `count` pending samples
+    /// ## Returns
+    /// Number of samples dropped
+    pub fn discard(&mut self, count: usize) -> usize {
+        let count = count.min(self.pending.len());
+        self.pending.drain(0..count);
+        count
+    }
+
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+impl CaptureSource for BlockBuffer {
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn channels(&self) -> usize {
+        self.channels
+    }
+
+    fn next_block(&mut self, block: &mut [f32]) -> Option<usize> {
+        let block_len = (self.block_size * self.channels).min(block.len());
+        let block_len = if self.flushed {
+            block_len.min(self.pending.len())
+        } else {
+            block_len
+        };
+        if block_len == 0 || self.pending.len() < block_len {
+            if self.pending.is_empty() {
+                self.flushed = false;
+            }
+            return None;
+        }
+        block
+            .iter_mut()
+            .zip(self.pending.drain(0..block_len))
+            .for_each(|(dst, src)| *dst = src);
+        Some(block_len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use adhoc_audio::WavCodec;
+    use std::io::Cursor;
+
+    #[test]
+    fn block_buffer_only_hands_out_complete_blocks() {
+        let mut buffer = BlockBuffer::new(4, 44_100, 1);
+        let mut block = [0.0; 4];
+
+        buffer.push(&[0.1, 0.2, 0.3]);
+        assert_eq!(buffer.next_block(&mut block), None);
+
+        buffer.push(&[0.4, 0.5]);
+        assert_eq!(buffer.next_block(&mut block), Some(4));
+        assert_eq!(block, [0.1, 0.2, 0.3, 0.4]);
+        assert_eq!(buffer.pending(), 1);
+
+        assert_eq!(buffer.discard(10), 1);
+        assert_eq!(buffer.pending(), 0);
+    }
+
+    #[test]
+    fn block_buffer_reports_resampled_rate() {
+        let mut buffer = BlockBuffer::new(1024, 48_000, 1).with_sample_rate(16_000);
+        assert_eq!(buffer.sample_rate(), 16_000);
+
+        buffer.push(&[0.0; 4800]);
+        // kernel lookahead holds a few samples back
+        assert!(buffer.pending() > 1500 && buffer.pending() <= 1600);
+    }
+
+    #[test]
+    fn flushed_block_buffers_hand_out_everything_that_was_pushed() {
+        let mut buffer = BlockBuffer::new(1024, 48_000, 2).with_sample_rate(16_000);
+        buffer.push(&[0.25; 2 * 4800]);
+        let mut block = vec![0.0; 2 * 1024];
+        let mut frames = 0;
+        while let Some(n) = buffer.next_block(&mut block) {
+            frames += n / 2;
+        }
+        assert_eq!(frames, 1024);
+
+        // the capture stopped, the end of the take is still in the resamplers and the last block
+        buffer.flush();
+        while let Some(n) = buffer.next_block(&mut block) {
+            frames += n / 2;
+        }
+        assert_eq!(frames, 1600);
+        assert_eq!(buffer.pending(), 0);
+
+        buffer.push(&[0.25; 2 * 300]);
+        assert_eq!(buffer.next_block(&mut block), None);
+    }
+
+    #[test]
+    fn block_buffer_resamples_channels_independently() {
+        let mut buffer = BlockBuffer::new(256, 48_000, 2).with_sample_rate(24_000);
+        let left = vec![0.5; 4800];
+        let right = vec![-0.25; 4800];
+        let mut interleaved = Vec::new();
+        math::interleave(&[left, right], &mut interleaved);
+        buffer.push(&interleaved);
+
+        let mut block = vec![0.0; 512];
+        buffer
+            .next_block(&mut block)
+            .expect("a block should be ready");
+        let channels = math::deinterleave(&block, 2);
+        // skip the start, the kernel is still ramping up from silence there
+        assert!(channels[0][64..].iter().all(|s| (s - 0.5).abs() < 0.01));
+        assert!(channels[1][64..].iter().all(|s| (s + 0.25).abs() < 0.01));
+    }
+
+    #[test]
+    fn gate_fades_around_pauses() {
+        let mut gate = RecordGate::new(1);
+        let mut encoded = Vec::new();
+        let statuses = [
+            RecordStatus::Recording,
+            RecordStatus::Recording,
+            RecordStatus::Paused,
+            RecordStatus::Paused,
+            RecordStatus::Recording,
+        ];
+        for &status in statuses.iter() {
+            let mut block = [1.0; 4];
+            if gate.apply(status, &mut block) {
+                encoded.extend_from_slice(&block);
+            }
+        }
+
+        // the paused block is dropped, the rest is spliced together with ramps
+        let third = 1.0 / 3.0;
+        let expected = [
+            [0.0, third, 2.0 * third, 1.0],
+            [1.0, 1.0, 1.0, 1.0],
+            [1.0, 2.0 * third, third, 0.0],
+            [0.0, third, 2.0 * third, 1.0],
+        ];
+        assert_eq!(encoded.len(), 16);
+        encoded
+            .iter()
+            .zip(expected.iter().flatten())
+            .for_each(|(a, b)| assert!((a - b).abs() < 1e-6));
+
+        assert!(gate.is_open());
+        gate.close();
+        let mut block = [1.0; 4];
+        assert!(!gate.apply(RecordStatus::Paused, &mut block));
+        assert!(gate.apply(RecordStatus::Recording, &mut block));
+        assert_eq!(block[0], 0.0);
+    }
+
+    #[test]
+    fn voice_gate_trims_the_silence_around_speech() {
+        const SAMPLE_RATE: u32 = 16_000;
+        let tone = |secs: f32| {
+            (0..(secs * SAMPLE_RATE as f32) as usize)
+                .map(|k| (k as f32 * 0.08).sin() * 0.3)
+                .collect::<Vec<_>>()
+        };
+        let silence = |secs: f32| vec![0.0; (secs * SAMPLE_RATE as f32) as usize];
+        let mut gate = VoiceGate::new(
+            VoiceActivation::default().with_stop_after_silence(Some(1.0)),
+            256,
+            SAMPLE_RATE,
+            1,
+        );
+
+        let mut microphone = BlockBuffer::new(256, SAMPLE_RATE, 1);
+        microphone.push(&silence(2.0));
+        assert_eq!(gate.feed(&mut microphone), (Some(0.0), None));
+        assert_eq!(gate.released().pending(), 0);
+
+        microphone.push(&tone(1.0));
+        microphone.push(&silence(0.5));
+        assert_eq!(gate.feed(&mut microphone).1, Some(VoiceEvent::SpeechEnded));
+        microphone.push(&tone(0.5));
+        assert_eq!(
+            gate.feed(&mut microphone).1,
+            Some(VoiceEvent::SpeechStarted)
+        );
+        microphone.push(&silence(3.0));
+        assert_eq!(
+            gate.feed(&mut microphone).1,
+            Some(VoiceEvent::SilenceTimeout)
+        );
+
+        // the padding on both ends and the pause in between are kept, the rest of the silence isn't
+        let kept = gate.released().pending() as f32 / SAMPLE_RATE as f32;
+        assert!((2.4..3.0).contains(&kept), "kept {} seconds", kept);
+    }
+
+    #[test]
+    fn stereo_channels_round_trip_through_wav() {
+        const SAMPLE_RATE: u32 = 8_000;
+        // a whole number of blocks, partial blocks stay pending
+        const FRAMES: usize = 8 * 1024;
+
+        // different content on each channel so any bleed between them shows up
+        let left = (0..FRAMES)
+            .map(|k| (k as f32 * 0.05).sin() * 0.5)
+            .collect::<Vec<_>>();
+        let right = (0..FRAMES)
+            .map(|k| if (k / 400) % 2 == 0 { 0.25 } else { -0.25 })
+            .collect::<Vec<_>>();
+        let mut interleaved = Vec::new();
+        math::interleave(&[&left, &right], &mut interleaved);
+
+        let mut microphone = BlockBuffer::new(1024, SAMPLE_RATE, 2);
+        microphone.push(&interleaved);
+        let mut codec = AdhocCodec::new().with_info(microphone.info());
+        record(&mut microphone, &mut codec, |_| true);
+
+        let mut upload = Vec::new();
+        codec.save_to(&mut upload).expect("serialization failed");
+        let mut received = AdhocCodec::load(&upload[..]).expect("bad recording");
+        assert_eq!(received.info().channels(), 2);
+
+        let mut wav = WavCodec::new(received.info());
+        let mut buffer = [0.0; 1024];
+        while let Some(n) = received.decode(&mut buffer) {
+            wav.encode(&buffer[0..n]);
+        }
+        let mut wav_file = Vec::new();
+        wav.save_to(&mut wav_file).expect("wav export failed");
+
+        let mut wav_source = WavSource::load(Cursor::new(wav_file)).expect("bad wav");
+        assert_eq!(wav_source.channels(), 2);
+        assert_eq!(wav_source.sample_rate(), SAMPLE_RATE);
+
+        let mut decoded = Vec::new();
+        let mut block = vec![0.0; wav_source.block_size() * 2];
+        while let Some(n) = wav_source.next_block(&mut block) {
+            decoded.extend_from_slice(&block[0..n]);
+        }
+
+        let channels = math::deinterleave(&decoded, 2);
+        assert!(channels[0].len() >= FRAMES);
+        assert!(math::compute_mse(&left, &channels[0]) < 1e-6);
+        assert!(math::compute_mse(&right, &channels[1]) < 1e-6);
+    }
+
+    #[test]
+    fn record_encode_upload_pipeline() {
+        let mut source = SyntheticSource::sine(440.0, 0.5)
+            .with_sample_rate(8_000)
+            .with_duration(0.5);
+
+        let mut codec = AdhocCodec::new().with_info(source.info());
+        let mut blocks = 0;
+        let samples_encoded = record(&mut source, &mut codec, |_| {
+            blocks += 1;
+            true
+        });
+        assert_eq!(samples_encoded, 4_000);
+        assert!(blocks > 0);
+
+        // what gets uploaded is the serialized codec
+        let mut upload = Vec::new();
+        codec.save_to(&mut upload).expect("serialization failed");
+
+        let mut received = AdhocCodec::load(&upload[..]).expect("upload is not a valid recording");
+        assert_eq!(received.info().frequency(), 8_000);
+        assert_eq!(received.info().channels(), 1);
+
+        let mut decoded = Vec::new();
+        let mut buffer = [0.0; 1024];
+        while let Some(n) = received.decode(&mut buffer) {
+            decoded.extend_from_slice(&buffer[0..n]);
+        }
+
+        let mut expected = vec![0.0; 4_000];
+        let mut reference = SyntheticSource::sine(440.0, 0.5)
+            .with_sample_rate(8_000)
+            .with_duration(0.5);
+        let mut cursor = 0;
+        while let Some(n) = reference.next_block(&mut expected[cursor..]) {
+            cursor += n;
+        }
+
+        assert!(decoded.len() >= expected.len());
+        assert!(math::compute_mse(&expected, &decoded) < 0.001);
+    }
+}
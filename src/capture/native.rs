@@ -0,0 +1,149 @@
+use super::CaptureSource;
+use adhoc_audio::{Streamable, WavCodec};
+use std::io::{Read, Seek};
+
+const DEFAULT_BLOCK_SIZE: usize = 1024;
+
+/// # Description
+/// Captures audio from a wav stream
+pub struct WavSource {
+    wav: WavCodec,
+    block_size: usize,
+}
+
+impl WavSource {
+    pub fn new(wav: WavCodec) -> Self {
+        Self {
+            wav,
+            block_size: DEFAULT_BLOCK_SIZE,
+        }
+    }
+
+    /// # Description
+    /// parses a wav file from `res`
+    pub fn load<Resource>(res: Resource) -> Result<Self, &'static str>
+    where
+        Resource: Read + Seek,
+    {
+        WavCodec::load(res).map(Self::new)
+    }
+
+    pub fn with_block_size(mut self, block_size: usize) -> Self {
+        self.block_size = block_size.max(1);
+        self
+    }
+}
+
+impl CaptureSource for WavSource {
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.wav.info().frequency() as u32
+    }
+
+    fn channels(&self) -> usize {
+        self.wav.info().channels()
+    }
+
+    fn next_block(&mut self, block: &mut [f32]) -> Option<usize> {
+        let block_len = (self.block_size * self.channels()).min(block.len());
+        self.wav.decode(&mut block[0..block_len])
+    }
+}
+
+/// # Description
+/// Generates a sine tone, handy for testing the pipeline without any hardware
+pub struct SyntheticSource {
+    frequency: f32,
+    amplitude: f32,
+    sample_rate: u32,
+    channels: usize,
+    block_size: usize,
+    /// total number of frames to generate, `None` means the tone never ends
+    frames_total: Option<usize>,
+    frames_generated: usize,
+}
+
+impl SyntheticSource {
+    /// ## Parameters
+    /// - `frequency` is in Hz
+    /// - `amplitude` ranges from 0.0 to 1.0
+    pub fn sine(frequency: f32, amplitude: f32) -> Self {
+        Self {
+            frequency,
+            amplitude,
+            sample_rate: 44_100,
+            channels: 1,
+            block_size: DEFAULT_BLOCK_SIZE,
+            frames_total: None,
+            frames_generated: 0,
+        }
+    }
+
+    pub fn with_sample_rate(mut self, sample_rate: u32) -> Self {
+        self.sample_rate = sample_rate;
+        self
+    }
+
+    /// # Description
+    /// every channel gets the same tone
+    pub fn with_channels(mut self, channels: usize) -> Self {
+        self.channels = channels.max(1);
+        self
+    }
+
+    pub fn with_block_size(mut self, block_size: usize) -> Self {
+        self.block_size = block_size.max(1);
+        self
+    }
+
+    /// # Description
+    /// stops the tone after `seconds`
+    pub fn with_duration(mut self, seconds: f32) -> Self {
+        self.frames_total = Some((seconds * self.sample_rate as f32) as usize);
+        self
+    }
+}
+
+impl CaptureSource for SyntheticSource {
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn channels(&self) -> usize {
+        self.channels
+    }
+
+    fn next_block(&mut self, block: &mut [f32]) -> Option<usize> {
+        let channels = self.channels;
+        let frames_remaining = self
+            .frames_total
+            .map(|total| total - self.frames_generated)
+            .unwrap_or(usize::MAX);
+        let frames = self
+            .block_size
+            .min(block.len() / channels)
+            .min(frames_remaining);
+
+        if frames == 0 {
+            return None;
+        }
+
+        let dt = 1.0 / self.sample_rate as f32;
+        let omega = 2.0 * std::f32::consts::PI * self.frequency;
+        for (k, frame) in block.chunks_exact_mut(channels).take(frames).enumerate() {
+            let t = (self.frames_generated + k) as f32 * dt;
+            let sample = (omega * t).sin() * self.amplitude;
+            frame.iter_mut().for_each(|s| *s = sample);
+        }
+        self.frames_generated += frames;
+
+        Some(frames * channels)
+    }
+}
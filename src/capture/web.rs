@@ -0,0 +1,246 @@
+use super::{BlockBuffer, CaptureSource};
+use crate::math;
+use crate::recorder::RecorderError;
+use crate::web_utils::closure_to_function;
+use js_sys::{Array, Float32Array, Object, Reflect};
+use std::{cell::RefCell, rc::Rc};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    console, AudioContext, AudioNode, AudioProcessingEvent, AudioWorkletNode,
+    AudioWorkletNodeOptions, Blob, BlobPropertyBag, ChannelCountMode, MessageEvent,
+    ScriptProcessorNode, Url,
+};
+
+pub const CAPTURE_BLOCK_SIZE: u32 = 1024;
+const WORKLET_PROCESSOR_NAME: &str = "recorder-processor";
+const WORKLET_SOURCE: &str = include_str!("../../js/recorder_worklet.js");
+
+/// # Description
+/// which web-audio node is used to pull samples off the microphone
+#[derive(Copy, Clone, PartialEq)]
+pub enum CaptureBackend {
+    /// capture runs on the audio rendering thread, blocks are posted back through a message port
+    Worklet,
+    /// deprecated main-thread capture, only used when the worklet can't be registered
+    ScriptProcessor,
+}
+
+/// # Description
+/// how a `WebCaptureSource` should capture
+#[derive(Copy, Clone)]
+pub struct CaptureConfig {
+    pub backend: CaptureBackend,
+    /// rate the captured audio gets resampled to, `None` keeps the `AudioContext` rate
+    pub sample_rate: Option<u32>,
+    /// number of input channels captured, the source is up/down-mixed to this count
+    pub channels: u32,
+}
+
+impl CaptureConfig {
+    pub fn new(backend: CaptureBackend) -> Self {
+        Self {
+            backend,
+            sample_rate: None,
+            channels: 1,
+        }
+    }
+
+    pub fn with_channels(mut self, channels: u32) -> Self {
+        self.channels = channels.max(1);
+        self
+    }
+
+    pub fn with_sample_rate(mut self, sample_rate: Option<u32>) -> Self {
+        self.sample_rate = sample_rate;
+        self
+    }
+}
+
+/// # Description
+/// a live capture node, one exists per active recording
+pub enum CaptureNode {
+    Worklet(AudioWorkletNode),
+    ScriptProcessor(ScriptProcessorNode),
+}
+impl CaptureNode {
+    pub fn as_audio_node(&self) -> &AudioNode {
+        match self {
+            Self::Worklet(node) => node,
+            Self::ScriptProcessor(node) => node,
+        }
+    }
+
+    /// # Description
+    /// stops the node from delivering blocks and detaches it from the audio graph
+    pub fn stop(&self) {
+        match self {
+            Self::Worklet(node) => {
+                if let Ok(port) = node.port() {
+                    port.set_onmessage(None);
+                    port.post_message(&JsValue::from_str("stop")).ok();
+                }
+            }
+            Self::ScriptProcessor(node) => node.set_onaudioprocess(None),
+        }
+        self.as_audio_node().disconnect().ok();
+    }
+}
+
+/// # Description
+/// loads the capture processor into the context's `AudioWorklet`
+/// ## Comments
+/// - the processor source is compiled into the binary and loaded through a blob url
+/// - the host page doesn't have to serve an extra file
+pub async fn register_recorder_worklet(ctx: &AudioContext) -> Result<(), JsValue> {
+    let source = Array::new();
+    source.push(&JsValue::from_str(WORKLET_SOURCE));
+    let options = BlobPropertyBag::new();
+    options.set_type("application/javascript");
+    let blob = Blob::new_with_str_sequence_and_options(&source, &options)?;
+    let url = Url::create_object_url_with_blob(&blob)?;
+    let registered = JsFuture::from(ctx.audio_worklet()?.add_module(&url)?).await;
+    Url::revoke_object_url(&url).ok();
+    registered.map(|_| ())
+}
+
+/// # Description
+/// Captures microphone audio through webaudio
+/// ## Comments
+/// - blocks are pushed into a `BlockBuffer` by the browser
+/// - `on_block_ready` gets called every time new samples arrive so the buffer can be drained
+pub struct WebCaptureSource {
+    node: CaptureNode,
+    buffer: Rc<RefCell<BlockBuffer>>,
+}
+
+impl WebCaptureSource {
+    /// # Description
+    /// creates a capture node described by `config` and connects `input` to it
+    pub fn new<CB>(
+        ctx: &AudioContext,
+        input: &AudioNode,
+        config: CaptureConfig,
+        mut on_block_ready: CB,
+    ) -> Result<Self, JsValue>
+    where
+        CB: FnMut(&mut BlockBuffer) + 'static,
+    {
+        let context_rate = ctx.sample_rate() as u32;
+        let channels = config.channels;
+        let buffer = Rc::new(RefCell::new(
+            BlockBuffer::new(CAPTURE_BLOCK_SIZE as usize, context_rate, channels as usize)
+                .with_sample_rate(config.sample_rate.unwrap_or(context_rate)),
+        ));
+
+        let node_buffer = buffer.clone();
+        let mut on_samples = move |samples: &[f32]| {
+            let mut buffer = node_buffer.borrow_mut();
+            buffer.push(samples);
+            on_block_ready(&mut buffer);
+        };
+
+        let node = match config.backend {
+            CaptureBackend::Worklet => {
+                let processor_options = Object::new();
+                Reflect::set(
+                    &processor_options,
+                    &JsValue::from_str("blockSize"),
+                    &JsValue::from(CAPTURE_BLOCK_SIZE),
+                )?;
+                Reflect::set(
+                    &processor_options,
+                    &JsValue::from_str("channels"),
+                    &JsValue::from(channels),
+                )?;
+                let options = AudioWorkletNodeOptions::new();
+                options.set_channel_count(channels);
+                options.set_channel_count_mode(ChannelCountMode::Explicit);
+                options.set_number_of_inputs(1);
+                options.set_number_of_outputs(1);
+                options.set_processor_options(Some(&processor_options));
+                let node =
+                    AudioWorkletNode::new_with_options(ctx, WORKLET_PROCESSOR_NAME, &options)?;
+                node.port()?
+                    .set_onmessage(Some(&closure_to_function(move |e: MessageEvent| {
+                        if let Ok(block) = e.data().dyn_into::<Float32Array>() {
+                            on_samples(&block.to_vec());
+                        }
+                    })));
+                CaptureNode::Worklet(node)
+            }
+            CaptureBackend::ScriptProcessor => {
+                let node = ctx.create_script_processor_with_buffer_size_and_number_of_input_channels_and_number_of_output_channels(CAPTURE_BLOCK_SIZE, channels, 1)?;
+                let mut microphone_samples = Vec::new();
+                node.set_onaudioprocess(Some(&closure_to_function(
+                    move |e: AudioProcessingEvent| {
+                        match read_input(&e, &mut microphone_samples) {
+                            Ok(()) => on_samples(&microphone_samples),
+                            // a block the browser couldn't hand over is skipped
+                            Err(err) => console::error_1(&err.to_string().into()),
+                        }
+                    },
+                )));
+                CaptureNode::ScriptProcessor(node)
+            }
+        };
+
+        input.connect_with_audio_node(node.as_audio_node())?;
+        node.as_audio_node()
+            .connect_with_audio_node(&ctx.destination())?;
+
+        Ok(Self { node, buffer })
+    }
+
+    pub fn node(&self) -> &CaptureNode {
+        &self.node
+    }
+
+    pub fn stop(&self) {
+        self.node.stop();
+    }
+
+    /// # Description
+    /// stops capturing and lets out what the buffer still holds back, see `BlockBuffer::flush`
+    /// ## Parameters
+    /// - `on_rest` drains the buffer one last time
+    pub fn finish<CB>(self, on_rest: CB)
+    where
+        CB: FnOnce(&mut BlockBuffer),
+    {
+        self.stop();
+        let mut buffer = self.buffer.borrow_mut();
+        buffer.flush();
+        on_rest(&mut buffer);
+    }
+}
+
+/// # Description
+/// interleaves the microphone input handed to a `ScriptProcessorNode` into `samples`
+fn read_input(e: &AudioProcessingEvent, samples: &mut Vec<f32>) -> Result<(), RecorderError> {
+    let input = e.input_buffer().map_err(RecorderError::audio)?;
+    let channel_data = (0..input.number_of_channels())
+        .map(|c| input.get_channel_data(c).unwrap_or_default())
+        .collect::<Vec<_>>();
+    samples.clear();
+    math::interleave(&channel_data, samples);
+    Ok(())
+}
+
+impl CaptureSource for WebCaptureSource {
+    fn block_size(&self) -> usize {
+        self.buffer.borrow().block_size()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.buffer.borrow().sample_rate()
+    }
+
+    fn channels(&self) -> usize {
+        self.buffer.borrow().channels()
+    }
+
+    fn next_block(&mut self, block: &mut [f32]) -> Option<usize> {
+        self.buffer.borrow_mut().next_block(block)
+    }
+}
@@ -0,0 +1,515 @@
+use crate::{
+    capture::{RecordStatus, VoiceActivation},
+    collections::Ptr,
+    recorder::{
+        input_devices, json_to_js, microphone_permission, CaptureConstraints, Recorder,
+        RecorderError, SessionConfig, WAV_MIME_TYPE,
+    },
+    recording::TrimConfig,
+    web_utils::{bytes_to_blob, bytes_to_blob_with_type},
+};
+use js_sys::{Array, Function, Object, Promise, Reflect};
+use std::{cell::RefCell, rc::Rc};
+use wasm_bindgen::{prelude::*, JsCast};
+use wasm_bindgen_futures::future_to_promise;
+use web_sys::Blob;
+
+/// # Description
+/// javascript callbacks a `JsRecorder` reports to
+/// ## Comments
+/// - kept behind an `Rc` so the callbacks can be swapped out while a recording is running
+#[derive(Default)]
+struct Callbacks {
+    on_level: RefCell<Option<Function>>,
+    on_state_change: RefCell<Option<Function>>,
+    on_error: RefCell<Option<Function>>,
+    on_voice: RefCell<Option<Function>>,
+}
+
+impl Callbacks {
+    fn emit(callback: &RefCell<Option<Function>>, value: &JsValue) {
+        if let Some(callback) = callback.borrow().as_ref() {
+            callback.call1(&JsValue::NULL, value).ok();
+        }
+    }
+}
+
+/// # Description
+/// The recorder as seen from javascript, a single recording driven from code instead of a button
+/// ## Comments
+/// - created with `await Recorder.new(options)`, every option is optional:
+/// - `sampleRate`, `channels`, `fieldName`, `chunkUrl` and `beep` configure the recording
+/// - `capture` holds the `getUserMedia` constraints the microphone is opened with, see
+///   `CaptureConstraints`
+/// - `idleRelease` is how many seconds the microphone stays open once nothing is being recorded,
+///   `false` keeps it open
+/// - `voiceActivated` only records while someone speaks, it stops after `silenceStop` seconds of
+///   silence (2 by default, `false` keeps it recording)
+/// - `trimSilence` cuts the silence before the first word and after the last one out of the blobs
+///   handed out, and shortens pauses to 1 second, a number of seconds instead of `true` changes
+///   how long pauses may last
+/// - `onLevel(peak)`, `onStateChange(state)`, `onVoice(speaking)` and `onError(error)` subscribe
+///   to its events
+/// - states are reported as `"recording"`, `"paused"` or `"stopped"`
+#[wasm_bindgen(js_name = Recorder)]
+pub struct JsRecorder {
+    recorder: Recorder,
+    session: Ptr,
+    callbacks: Rc<Callbacks>,
+}
+
+#[wasm_bindgen(js_class = Recorder)]
+impl JsRecorder {
+    /// # Description
+    /// sets up a recording described by `options`, the microphone is asked for on `start`
+    pub async fn new(options: JsValue) -> Result<JsRecorder, JsValue> {
+        let mut config = SessionConfig::default()
+            .with_beep(option(&options, "beep").and_then(|v| v.as_bool()) == Some(true));
+        if let Some(rate) = option(&options, "sampleRate").and_then(|v| v.as_f64()) {
+            config = config.with_sample_rate(Some(rate as u32).filter(|&rate| rate > 0));
+        }
+        if let Some(channels) = option(&options, "channels").and_then(|v| v.as_f64()) {
+            config = config.with_channels(channels as u32);
+        }
+        if let Some(name) = option(&options, "fieldName").and_then(|v| v.as_string()) {
+            config = config.with_field_name(name.trim());
+        }
+        if let Some(url) = option(&options, "chunkUrl").and_then(|v| v.as_string()) {
+            config = config.with_chunk_url(Some(url));
+        }
+        if option(&options, "voiceActivated").and_then(|v| v.as_bool()) == Some(true) {
+            let voice = match option(&options, "silenceStop") {
+                Some(secs) => VoiceActivation::default().with_stop_after_silence(
+                    secs.as_f64()
+                        .map(|secs| secs as f32)
+                        .filter(|&secs| secs > 0.0),
+                ),
+                None => VoiceActivation::default(),
+            };
+            config = config.with_voice_activation(Some(voice));
+        }
+        if let Some(trim) = option(&options, "trimSilence") {
+            let max_gap = trim.as_f64().filter(|&secs| secs >= 0.0);
+            config = config.with_trim_silence(match (trim.as_bool(), max_gap) {
+                (_, Some(secs)) => Some(TrimConfig::default().with_max_gap(Some(secs as f32))),
+                (Some(true), None) => Some(TrimConfig::default()),
+                _ => None,
+            });
+        }
+
+        let callbacks = Rc::new(Callbacks {
+            on_level: RefCell::new(callback(&options, "onLevel")),
+            on_state_change: RefCell::new(callback(&options, "onStateChange")),
+            on_error: RefCell::new(callback(&options, "onError")),
+            on_voice: RefCell::new(callback(&options, "onVoice")),
+        });
+
+        let recorder = match capture_constraints(option(&options, "capture")) {
+            Ok(constraints) => Recorder::with_constraints(constraints).await,
+            Err(err) => Err(err),
+        };
+        let recorder = match recorder {
+            Ok(recorder) => recorder,
+            Err(err) => {
+                let err = JsValue::from(err);
+                Callbacks::emit(&callbacks.on_error, &err);
+                return Err(err);
+            }
+        };
+        if let Some(idle_release) = option(&options, "idleRelease") {
+            recorder.set_idle_release(idle_release.as_f64());
+        }
+        let session = recorder.create_session(config);
+
+        let level_callbacks = callbacks.clone();
+        recorder.set_level_listener(
+            session,
+            Some(Rc::new(move |peak: f32| {
+                Callbacks::emit(&level_callbacks.on_level, &JsValue::from_f64(peak as f64))
+            })),
+        );
+        let status_callbacks = callbacks.clone();
+        recorder.set_status_listener(
+            session,
+            Some(Rc::new(move |status: RecordStatus| {
+                Callbacks::emit(
+                    &status_callbacks.on_state_change,
+                    &JsValue::from_str(status_name(status)),
+                )
+            })),
+        );
+
+        let voice_callbacks = callbacks.clone();
+        recorder.set_voice_listener(
+            session,
+            Some(Rc::new(move |speaking: bool| {
+                Callbacks::emit(&voice_callbacks.on_voice, &JsValue::from_bool(speaking))
+            })),
+        );
+
+        Ok(Self {
+            recorder,
+            session,
+            callbacks,
+        })
+    }
+
+    /// # Description
+    /// starts recording, or resumes a paused recording
+    /// ## Returns
+    /// a promise that settles once recording started, the user is asked for the microphone first
+    /// when it isn't open
+    pub fn start(&self) -> Promise {
+        let started = self.recorder.record_when_open(self.session);
+        let callbacks = self.callbacks.clone();
+        future_to_promise(async move {
+            match started.await {
+                Ok(_) => Ok(JsValue::UNDEFINED),
+                Err(err) => {
+                    let err = JsValue::from(err);
+                    Callbacks::emit(&callbacks.on_error, &err);
+                    Err(err)
+                }
+            }
+        })
+    }
+
+    /// # Description
+    /// pauses the recording, `start` resumes it
+    pub fn pause(&self) -> Result<(), JsValue> {
+        let status = self.recorder.pause(self.session);
+        self.report(status).map(|_| ())
+    }
+
+    /// # Description
+    /// stops the recording, starting again appends to what was recorded so far
+    pub fn stop(&self) -> Result<(), JsValue> {
+        let status = self.recorder.stop(self.session);
+        self.report(status).map(|_| ())
+    }
+
+    /// # Description
+    /// stops recording and plays back what has been recorded so far
+    pub fn play(&self) -> Result<(), JsValue> {
+        let played = self.recorder.play(self.session);
+        self.report(played)
+    }
+
+    /// # Description
+    /// stops playback, `play` carries on from the same position
+    #[wasm_bindgen(js_name = stopPlayback)]
+    pub fn stop_playback(&self) {
+        self.recorder.stop_playback(self.session);
+    }
+
+    /// # Description
+    /// moves playback to `position` seconds into the recording
+    pub fn seek(&self, position: f64) -> Result<(), JsValue> {
+        let seeked = self.recorder.seek(self.session, position);
+        self.report(seeked)
+    }
+
+    /// # Description
+    /// stops the recording and throws it away
+    pub fn rerecord(&self) -> Result<(), JsValue> {
+        let status = self.recorder.discard(self.session);
+        self.report(status).map(|_| ())
+    }
+
+    /// # Description
+    /// playback position in seconds
+    #[wasm_bindgen(getter)]
+    pub fn position(&self) -> f64 {
+        self.recorder
+            .playback(self.session)
+            .map_or(0.0, |playback| playback.position)
+    }
+
+    /// # Description
+    /// length of the recording in seconds, known once it has been played or scrubbed
+    #[wasm_bindgen(getter)]
+    pub fn duration(&self) -> f64 {
+        self.recorder
+            .playback(self.session)
+            .map_or(0.0, |playback| playback.duration)
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn playing(&self) -> bool {
+        self.recorder
+            .playback(self.session)
+            .is_some_and(|playback| playback.playing)
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn state(&self) -> String {
+        self.recorder
+            .status(self.session)
+            .map(status_name)
+            .unwrap_or("stopped")
+            .to_string()
+    }
+
+    /// # Description
+    /// stops the recording and hands it out as a `Blob`, the recorder starts over afterwards
+    /// ## Parameters
+    /// - `format` is `"adhoc"` (the default) or `"wav"`
+    #[wasm_bindgen(js_name = takeBlob)]
+    pub fn take_blob(&self, format: Option<String>) -> Result<Blob, JsValue> {
+        let blob = self
+            .recorder
+            .stop(self.session)
+            .and_then(|_| self.recording_blob(format.as_deref()))
+            .and_then(|blob| self.recorder.take_recording(self.session).map(|_| blob));
+        self.report(blob)
+    }
+
+    /// # Description
+    /// hands out a copy of what has been recorded so far as a `Blob`, the recording carries on
+    /// ## Parameters
+    /// - `format` is `"adhoc"` or `"wav"` (the default), a wav blob plays in an `<audio>` element
+    #[wasm_bindgen(js_name = exportBlob)]
+    pub fn export_blob(&self, format: Option<String>) -> Result<Blob, JsValue> {
+        let blob = self.recording_blob(Some(format.as_deref().unwrap_or("wav")));
+        self.report(blob)
+    }
+
+    /// # Description
+    /// stops the recording and finishes its chunked upload, the recorder starts over afterwards
+    /// ## Returns
+    /// a promise of the id the server stored the recording under
+    #[wasm_bindgen(js_name = finishUpload)]
+    pub fn finish_upload(&self) -> Result<Promise, JsValue> {
+        let uploader = self
+            .recorder
+            .take_upload(self.session)
+            .and_then(|uploader| {
+                uploader.ok_or_else(|| {
+                    RecorderError::Upload("the recorder was created without a chunkUrl".to_string())
+                })
+            });
+        let uploader = self.report(uploader)?;
+        let callbacks = self.callbacks.clone();
+        Ok(future_to_promise(async move {
+            match uploader.finish().await {
+                Ok(id) => Ok(JsValue::from_str(&id)),
+                Err(err) => {
+                    let err = JsValue::from(err);
+                    Callbacks::emit(&callbacks.on_error, &err);
+                    Err(err)
+                }
+            }
+        }))
+    }
+
+    /// # Description
+    /// whether the page may record from the microphone, asking doesn't prompt the user
+    /// ## Returns
+    /// a promise of `"granted"`, `"denied"`, `"prompt"` or `"unknown"`
+    #[wasm_bindgen(js_name = microphonePermission)]
+    pub fn microphone_permission() -> Promise {
+        future_to_promise(async { Ok(JsValue::from_str(microphone_permission().await.name())) })
+    }
+
+    /// # Description
+    /// whether the microphone is open right now
+    #[wasm_bindgen(getter = microphoneOpen)]
+    pub fn microphone_open(&self) -> bool {
+        self.recorder.microphone_open()
+    }
+
+    /// # Description
+    /// every microphone the browser knows about
+    /// ## Returns
+    /// a promise of an array of `{ id, label, groupId }`
+    #[wasm_bindgen(js_name = inputDevices)]
+    pub fn input_devices(&self) -> Promise {
+        let callbacks = self.callbacks.clone();
+        future_to_promise(async move {
+            match input_devices().await {
+                Ok(devices) => Ok(devices
+                    .iter()
+                    .map(|device| {
+                        let entry = Object::new();
+                        Reflect::set(&entry, &"id".into(), &device.id.as_str().into()).ok();
+                        Reflect::set(&entry, &"label".into(), &device.label.as_str().into()).ok();
+                        Reflect::set(&entry, &"groupId".into(), &device.group_id.as_str().into())
+                            .ok();
+                        entry
+                    })
+                    .collect::<Array>()
+                    .into()),
+                Err(err) => {
+                    let err = JsValue::from(err);
+                    Callbacks::emit(&callbacks.on_error, &err);
+                    Err(err)
+                }
+            }
+        })
+    }
+
+    /// # Description
+    /// records from the microphone with the given `id` from now on, the choice is remembered
+    /// ## Parameters
+    /// - `id` comes from `inputDevices`, leaving it out goes back to the default microphone
+    /// ## Returns
+    /// a promise that settles once the microphone is in use, or right away while it's closed
+    #[wasm_bindgen(js_name = selectInput)]
+    pub fn select_input(&self, id: Option<String>) -> Promise {
+        let selected = self.recorder.select_input(id.as_deref());
+        let callbacks = self.callbacks.clone();
+        future_to_promise(async move {
+            match selected.await {
+                Ok(_) => Ok(JsValue::UNDEFINED),
+                Err(err) => {
+                    let err = JsValue::from(err);
+                    Callbacks::emit(&callbacks.on_error, &err);
+                    Err(err)
+                }
+            }
+        })
+    }
+
+    /// # Description
+    /// id of the microphone in use, `null` for the default one
+    #[wasm_bindgen(getter = inputDevice)]
+    pub fn input_device(&self) -> Option<String> {
+        self.recorder.input_device()
+    }
+
+    /// # Description
+    /// reopens the microphone with new constraints, like `{ echoCancellation: false }`
+    /// ## Returns
+    /// a promise that settles once the microphone is in use again, or right away while it's closed
+    #[wasm_bindgen(js_name = setConstraints)]
+    pub fn set_constraints(&self, capture: JsValue) -> Promise {
+        let applied = capture_constraints(Some(capture))
+            .map(|constraints| self.recorder.set_constraints(constraints));
+        let callbacks = self.callbacks.clone();
+        future_to_promise(async move {
+            let applied = match applied {
+                Ok(applied) => applied.await,
+                Err(err) => Err(err),
+            };
+            match applied {
+                Ok(_) => Ok(JsValue::UNDEFINED),
+                Err(err) => {
+                    let err = JsValue::from(err);
+                    Callbacks::emit(&callbacks.on_error, &err);
+                    Err(err)
+                }
+            }
+        })
+    }
+
+    /// # Description
+    /// what the browser actually applied to the microphone, like
+    /// `{ deviceId, echoCancellation, noiseSuppression, autoGainControl, channelCount, sampleRate,
+    /// latency }`, members the browser doesn't report are `null`
+    #[wasm_bindgen(getter = captureSettings)]
+    pub fn capture_settings(&self) -> Result<JsValue, JsValue> {
+        let settings = self
+            .recorder
+            .capture_settings()
+            .and_then(|settings| {
+                serde_json::to_value(settings).map_err(|err| RecorderError::Audio(err.to_string()))
+            })
+            .and_then(|settings| json_to_js(&settings));
+        self.report(settings)
+    }
+
+    #[wasm_bindgen(setter = onlevel)]
+    pub fn set_on_level(&self, callback: Option<Function>) {
+        *self.callbacks.on_level.borrow_mut() = callback;
+    }
+
+    #[wasm_bindgen(setter = onstatechange)]
+    pub fn set_on_state_change(&self, callback: Option<Function>) {
+        *self.callbacks.on_state_change.borrow_mut() = callback;
+    }
+
+    #[wasm_bindgen(setter = onvoice)]
+    pub fn set_on_voice(&self, callback: Option<Function>) {
+        *self.callbacks.on_voice.borrow_mut() = callback;
+    }
+
+    #[wasm_bindgen(setter = onerror)]
+    pub fn set_on_error(&self, callback: Option<Function>) {
+        *self.callbacks.on_error.borrow_mut() = callback;
+    }
+
+    /// # Description
+    /// discards the recording and releases the microphone
+    pub fn destroy(self) {
+        self.recorder.remove_session(self.session);
+        self.recorder.destroy();
+    }
+}
+
+impl JsRecorder {
+    /// # Description
+    /// encodes the recording so far into a `Blob` of the given format
+    fn recording_blob(&self, format: Option<&str>) -> Result<Blob, RecorderError> {
+        match format.unwrap_or("adhoc") {
+            "adhoc" => self
+                .recorder
+                .recording(self.session)
+                .map(|bytes| bytes_to_blob(&bytes)),
+            "wav" => self
+                .recorder
+                .export_wav(self.session)
+                .map(|wav| bytes_to_blob_with_type(&wav, WAV_MIME_TYPE)),
+            format => Err(RecorderError::Codec(format!(
+                "unsupported recording format: {}",
+                format
+            ))),
+        }
+    }
+
+    /// # Description
+    /// forwards errors to `onError` before handing them back to the caller
+    fn report<T>(&self, result: Result<T, RecorderError>) -> Result<T, JsValue> {
+        result.map_err(|err| {
+            let err = JsValue::from(err);
+            Callbacks::emit(&self.callbacks.on_error, &err);
+            err
+        })
+    }
+}
+
+fn status_name(status: RecordStatus) -> &'static str {
+    match status {
+        RecordStatus::Recording => "recording",
+        RecordStatus::Paused => "paused",
+        RecordStatus::Stopped => "stopped",
+    }
+}
+
+/// # Description
+/// reads `key` off a javascript options object, `null` and `undefined` count as missing
+fn option(options: &JsValue, key: &str) -> Option<JsValue> {
+    if !options.is_object() {
+        return None;
+    }
+    Reflect::get(options, &JsValue::from_str(key))
+        .ok()
+        .filter(|value| !value.is_undefined() && !value.is_null())
+}
+
+/// # Description
+/// reads the `capture` option, missing constraints keep the browser's defaults
+fn capture_constraints(capture: Option<JsValue>) -> Result<CaptureConstraints, RecorderError> {
+    match capture.filter(|capture| !capture.is_undefined() && !capture.is_null()) {
+        Some(capture) => js_sys::JSON::stringify(&capture)
+            .map_err(RecorderError::audio)?
+            .as_string()
+            .map_or(Ok(CaptureConstraints::default()), |capture| {
+                CaptureConstraints::from_json(&capture)
+            }),
+        None => Ok(CaptureConstraints::default()),
+    }
+}
+
+fn callback(options: &JsValue, key: &str) -> Option<Function> {
+    option(options, key).and_then(|value| value.dyn_into::<Function>().ok())
+}
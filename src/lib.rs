@@ -1,3 +1,9 @@
+pub mod capture;
 pub mod collections; 
+pub mod js_api;
 pub mod math; 
+pub mod offline;
+pub mod recorder;
+pub mod recording;
+pub mod upload;
 pub mod web_utils;
\ No newline at end of file
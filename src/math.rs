@@ -1,10 +1,12 @@
 pub mod noise;
 pub mod parabola;
 pub mod signal;
+pub mod vad;
 
 pub use noise::*;
 pub use parabola::*;
 pub use signal::*;
+pub use vad::*;
 
 
 pub type Vec<T,const N:usize> = [T;N]; 
@@ -53,4 +53,207 @@ pub fn scale_signal<'a>(input:&[f32],dst_len:usize,output:&'a mut [f32])->&'a mu
         output[k] = input[w0]*(1.0-t) + input[w1]*t;
     }
     &mut output[0..dst_len]
-}
\ No newline at end of file
+}
+
+/// # Description
+/// applies a linear gain ramp from `from` to `to` across interleaved `samples`
+/// ## Comments
+/// - every sample in a frame gets the same gain so channels stay in sync
+pub fn fade(samples: &mut [f32], num_channels: usize, from: f32, to: f32) {
+    let num_channels = num_channels.max(1);
+    let frames = samples.len() / num_channels;
+    let denom = frames.saturating_sub(1).max(1) as f32;
+    samples
+        .chunks_exact_mut(num_channels)
+        .enumerate()
+        .for_each(|(k, frame)| {
+            let t = k as f32 / denom;
+            let gain = from * (1.0 - t) + to * t;
+            frame.iter_mut().for_each(|s| *s *= gain);
+        });
+}
+
+/// # Description
+/// interleaves `channels` into `output` (`L R L R ...` for stereo)
+/// ## Comments
+/// - channels are truncated to the shortest one so every frame is complete
+pub fn interleave<C>(channels: &[C], output: &mut Vec<f32>)
+where
+    C: AsRef<[f32]>,
+{
+    let frames = channels
+        .iter()
+        .map(|c| c.as_ref().len())
+        .min()
+        .unwrap_or(0);
+    output.reserve(frames * channels.len());
+    for frame in 0..frames {
+        output.extend(channels.iter().map(|c| c.as_ref()[frame]));
+    }
+}
+
+/// # Description
+/// splits interleaved `samples` into one `Vec` per channel
+pub fn deinterleave(samples: &[f32], num_channels: usize) -> Vec<Vec<f32>> {
+    let mut channels = vec![Vec::with_capacity(samples.len() / num_channels.max(1)); num_channels];
+    samples
+        .chunks_exact(num_channels.max(1))
+        .for_each(|frame| {
+            channels
+                .iter_mut()
+                .zip(frame.iter())
+                .for_each(|(channel, &sample)| channel.push(sample))
+        });
+    channels
+}
+
+/// # Description
+/// A streaming band-limited resampler (windowed-sinc interpolation)
+/// ## Comments
+/// - when downsampling the kernel is stretched so content above the new nyquist gets filtered out
+/// - output lags input by `HALF_TAPS` samples, call `flush(..)` at the end of a stream to get them back
+pub struct Resampler {
+    /// input samples consumed for every output sample
+    step: f64,
+    /// cutoff frequency relative to the input nyquist
+    cutoff: f32,
+    /// half width of the kernel measured in input samples
+    width: usize,
+    history: Vec<f32>,
+    /// position of the next output sample in `history`
+    position: f64,
+}
+
+impl Resampler {
+    const HALF_TAPS: usize = 16;
+
+    pub fn new(input_rate: u32, output_rate: u32) -> Self {
+        let step = input_rate as f64 / output_rate as f64;
+        let cutoff = (output_rate as f32 / input_rate as f32).min(1.0);
+        let width = (Self::HALF_TAPS as f32 / cutoff).ceil() as usize;
+        Self {
+            step,
+            cutoff,
+            width,
+            history: vec![0.0; width],
+            position: width as f64,
+        }
+    }
+
+    /// # Description
+    /// resamples `input` and appends the result to `output`
+    pub fn process(&mut self, input: &[f32], output: &mut Vec<f32>) {
+        self.history.extend_from_slice(input);
+
+        while self.position.floor() as usize + self.width < self.history.len() {
+            output.push(self.interpolate(self.position));
+            self.position += self.step;
+        }
+
+        // forget samples the kernel will never touch again
+        let consumed = (self.position.floor() as usize).saturating_sub(self.width);
+        self.history.drain(0..consumed);
+        self.position -= consumed as f64;
+    }
+
+    /// # Description
+    /// pushes the samples still held back by the kernel into `output`
+    pub fn flush(&mut self, output: &mut Vec<f32>) {
+        let padding = vec![0.0; self.width + 1];
+        let end = self.history.len() as f64;
+        self.history.extend_from_slice(&padding);
+        while self.position < end {
+            output.push(self.interpolate(self.position));
+            self.position += self.step;
+        }
+        self.reset();
+    }
+
+    pub fn reset(&mut self) {
+        self.history.clear();
+        self.history.resize(self.width, 0.0);
+        self.position = self.width as f64;
+    }
+
+    fn interpolate(&self, position: f64) -> f32 {
+        let center = position.floor() as isize;
+        let width = self.width as isize;
+        let lbound = (center - width + 1).max(0);
+        let ubound = (center + width).min(self.history.len() as isize - 1);
+
+        let mut sum = 0.0;
+        for k in lbound..=ubound {
+            let x = (position - k as f64) as f32;
+            let window = 0.5 + 0.5 * (std::f32::consts::PI * x / self.width as f32).cos();
+            sum += self.history[k as usize] * self.cutoff * sinc(self.cutoff * x) * window;
+        }
+        sum
+    }
+}
+
+/// normalized sinc, `sin(pi*x)/(pi*x)`
+pub fn sinc(x: f32) -> f32 {
+    if x.abs() < 1e-6 {
+        1.0
+    } else {
+        let pi_x = std::f32::consts::PI * x;
+        pi_x.sin() / pi_x
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tone(freq: f32, sample_rate: u32, len: usize) -> Vec<f32> {
+        let omega = 2.0 * std::f32::consts::PI * freq / sample_rate as f32;
+        (0..len).map(|k| (omega * k as f32).sin() * 0.5).collect()
+    }
+
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn interleave_round_trip() {
+        let left = [1.0, 2.0, 3.0];
+        let right = [-1.0, -2.0, -3.0, -4.0];
+        let mut interleaved = Vec::new();
+        interleave(&[&left[..], &right[..]], &mut interleaved);
+        assert_eq!(interleaved, [1.0, -1.0, 2.0, -2.0, 3.0, -3.0]);
+
+        let channels = deinterleave(&interleaved, 2);
+        assert_eq!(channels[0], left);
+        assert_eq!(channels[1], right[0..3]);
+    }
+
+    #[test]
+    fn resampled_tone_keeps_its_pitch() {
+        let input = tone(1000.0, 48_000, 48_000);
+        let mut resampler = Resampler::new(48_000, 44_100);
+        let mut output = Vec::new();
+        // feed it in uneven blocks like a browser would
+        for block in input.chunks(1000) {
+            resampler.process(block, &mut output);
+        }
+        resampler.flush(&mut output);
+
+        assert!((output.len() as isize - 44_100).abs() <= 1);
+
+        let expected = tone(1000.0, 44_100, output.len());
+        // skip the edges, the kernel sees zero padding there
+        let mse = crate::math::compute_mse(&expected[64..44_000], &output[64..44_000]);
+        assert!(mse < 1e-4, "mse = {}", mse);
+    }
+
+    #[test]
+    fn downsampling_removes_content_above_nyquist() {
+        // 12kHz can't be represented at 16kHz, it should be filtered out instead of aliasing to 4kHz
+        let input = tone(12_000.0, 48_000, 48_000);
+        let mut resampler = Resampler::new(48_000, 16_000);
+        let mut output = Vec::new();
+        resampler.process(&input, &mut output);
+
+        assert!(rms(&output[64..]) < 0.01, "rms = {}", rms(&output[64..]));
+    }
+}
@@ -0,0 +1,221 @@
+/// levels are clamped to this, digital silence would be `-inf` otherwise
+const LEVEL_FLOOR_IN_DB: f32 = -100.0;
+/// length of the frames the detector looks at
+const FRAME_DURATION_IN_SECS: f32 = 0.02;
+/// how slowly the noise floor follows a level rising above it
+const FLOOR_RISE_TIME_IN_SECS: f32 = 3.0;
+
+/// # Description
+/// peak amplitude of `samples`, `None` when there are none
+pub fn peak_amplitude(samples: &[f32]) -> Option<f32> {
+    samples.iter().map(|s| s.abs()).reduce(f32::max)
+}
+
+/// # Description
+/// root mean square level of `samples` in dBFS
+pub fn rms_level_in_db(samples: &[f32]) -> f32 {
+    if samples.is_empty() {
+        return LEVEL_FLOOR_IN_DB;
+    }
+    let mean_square = samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32;
+    (10.0 * mean_square.log10()).max(LEVEL_FLOOR_IN_DB)
+}
+
+/// # Description
+/// fraction of neighbouring `samples` that change sign
+/// ## Comments
+/// - voiced speech crosses zero far less often than hiss or fricatives do
+pub fn zero_crossing_rate(samples: &[f32]) -> f32 {
+    if samples.len() < 2 {
+        return 0.0;
+    }
+    let crossings = samples
+        .windows(2)
+        .filter(|pair| (pair[0] >= 0.0) != (pair[1] >= 0.0))
+        .count();
+    crossings as f32 / (samples.len() - 1) as f32
+}
+
+/// # Description
+/// what counts as speech for a `VoiceActivityDetector`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct VadConfig {
+    /// speech has to be louder than this (in dBFS), however quiet the room is
+    pub min_level_in_db: f32,
+    /// how far above the noise floor speech has to rise to start
+    pub margin_in_db: f32,
+    /// how far below the start threshold the level has to drop for speech to end
+    pub hysteresis_in_db: f32,
+    /// frames crossing zero more often than this (per second) are taken for noise when speech
+    /// hasn't started yet
+    pub max_zero_crossings_per_sec: f32,
+    /// how long the level has to stay up before speech starts
+    pub attack_in_secs: f32,
+    /// how long the level has to stay down before speech ends
+    pub release_in_secs: f32,
+}
+
+impl Default for VadConfig {
+    fn default() -> Self {
+        Self {
+            min_level_in_db: -50.0,
+            margin_in_db: 10.0,
+            hysteresis_in_db: 6.0,
+            max_zero_crossings_per_sec: 6000.0,
+            attack_in_secs: 0.06,
+            release_in_secs: 0.25,
+        }
+    }
+}
+
+/// # Description
+/// Tells speech apart from silence in a stream of interleaved samples
+/// ## Comments
+/// - the stream is mixed down to mono and looked at in short frames
+/// - a frame is taken for speech when its energy rises above the noise floor and it doesn't cross
+///   zero as often as noise does, once speech started only its energy has to stay up
+/// - the noise floor follows the quiet parts of the stream, so a noisy room needs louder speech
+pub struct VoiceActivityDetector {
+    config: VadConfig,
+    channels: usize,
+    sample_rate: u32,
+    frame: Vec<f32>,
+    frame_len: usize,
+    noise_floor_in_db: f32,
+    speaking: bool,
+    /// how long the frames have been disagreeing with `speaking`
+    pending_in_secs: f32,
+}
+
+impl VoiceActivityDetector {
+    pub fn new(config: VadConfig, sample_rate: u32, channels: usize) -> Self {
+        let frame_len = ((sample_rate as f32 * FRAME_DURATION_IN_SECS) as usize).max(1);
+        Self {
+            config,
+            channels: channels.max(1),
+            sample_rate,
+            frame: Vec::with_capacity(frame_len),
+            frame_len,
+            noise_floor_in_db: LEVEL_FLOOR_IN_DB,
+            speaking: false,
+            pending_in_secs: 0.0,
+        }
+    }
+
+    /// # Description
+    /// feeds interleaved `samples` to the detector
+    /// ## Returns
+    /// whether speech is going on after the last complete frame
+    pub fn process(&mut self, samples: &[f32]) -> bool {
+        for frame in samples.chunks_exact(self.channels) {
+            self.frame
+                .push(frame.iter().sum::<f32>() / self.channels as f32);
+            if self.frame.len() == self.frame_len {
+                self.classify_frame();
+                self.frame.clear();
+            }
+        }
+        self.speaking
+    }
+
+    pub fn speaking(&self) -> bool {
+        self.speaking
+    }
+
+    /// level (in dBFS) the quiet parts of the stream sit at
+    pub fn noise_floor_in_db(&self) -> f32 {
+        self.noise_floor_in_db
+    }
+
+    /// # Description
+    /// forgets everything heard so far
+    pub fn reset(&mut self) {
+        self.frame.clear();
+        self.noise_floor_in_db = LEVEL_FLOOR_IN_DB;
+        self.speaking = false;
+        self.pending_in_secs = 0.0;
+    }
+
+    fn classify_frame(&mut self) {
+        let frame_secs = self.frame_len as f32 / self.sample_rate as f32;
+        let level = rms_level_in_db(&self.frame);
+        let crossings_per_sec = zero_crossing_rate(&self.frame) * self.sample_rate as f32;
+        let start_threshold =
+            (self.noise_floor_in_db + self.config.margin_in_db).max(self.config.min_level_in_db);
+
+        let voiced = if self.speaking {
+            level > start_threshold - self.config.hysteresis_in_db
+        } else {
+            level > start_threshold && crossings_per_sec <= self.config.max_zero_crossings_per_sec
+        };
+        if voiced == self.speaking {
+            self.pending_in_secs = 0.0;
+        } else {
+            self.pending_in_secs += frame_secs;
+            let needed = if self.speaking {
+                self.config.release_in_secs
+            } else {
+                self.config.attack_in_secs
+            };
+            if self.pending_in_secs >= needed {
+                self.speaking = voiced;
+                self.pending_in_secs = 0.0;
+            }
+        }
+
+        // the floor drops right away but only creeps up, and stays put while someone speaks
+        if level < self.noise_floor_in_db {
+            self.noise_floor_in_db = level;
+        } else if !self.speaking {
+            let rise = (frame_secs / FLOOR_RISE_TIME_IN_SECS).min(1.0);
+            self.noise_floor_in_db += (level - self.noise_floor_in_db) * rise;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 16_000;
+
+    fn tone(secs: f32, amplitude: f32) -> Vec<f32> {
+        (0..(secs * RATE as f32) as usize)
+            .map(|k| {
+                amplitude * (2.0 * std::f32::consts::PI * 220.0 * k as f32 / RATE as f32).sin()
+            })
+            .collect()
+    }
+
+    /// a deterministic stand in for white noise
+    fn hiss(secs: f32, amplitude: f32) -> Vec<f32> {
+        let mut seed = 0x2545_f491u32;
+        (0..(secs * RATE as f32) as usize)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                amplitude * (seed as f32 / u32::MAX as f32 * 2.0 - 1.0)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn speech_starts_on_voice_and_outlasts_short_dips() {
+        let mut vad = VoiceActivityDetector::new(VadConfig::default(), RATE, 1);
+
+        assert!(!vad.process(&hiss(1.0, 0.001)));
+        // loud hiss crosses zero too often to start speech
+        assert!(!vad.process(&hiss(0.5, 0.3)));
+        assert!(vad.process(&tone(0.5, 0.3)));
+
+        // a dip shorter than the release and a level inside the hysteresis band keep it going
+        assert!(vad.process(&hiss(0.1, 0.001)));
+        assert!(vad.process(&tone(0.5, 0.3)));
+        assert!(vad.process(&tone(0.5, 0.15)));
+
+        assert!(!vad.process(&hiss(0.5, 0.001)));
+        assert!(vad.noise_floor_in_db() < -50.0);
+        assert_eq!(peak_amplitude(&[0.1, -0.5, 0.25]), Some(0.5));
+    }
+}
@@ -0,0 +1,182 @@
+// Recordings kept in the browser
+//
+// A form whose recordings couldn't be sent because the server was out of reach is stored in
+// IndexedDB, recordings included, so the take survives a reload. Queued submissions are sent
+// again once the browser is back online, or the next time a `Recorder` starts up.
+//
+// Recordings are also autosaved into IndexedDB while they're being recorded, so a tab that
+// crashes or gets closed mid-recording leaves something to recover on the next visit.
+
+mod autosave;
+mod web;
+
+pub use autosave::*;
+pub use web::*;
+
+use crate::{recorder::RecorderError, upload::is_transient_status};
+use serde::{Deserialize, Serialize};
+
+/// a tab sending a queued submission holds on to it for this long at most, after that it's
+/// taken to have gone away mid-send and the submission is up for grabs again
+pub const QUEUE_CLAIM_EXPIRY_IN_SECS: f32 = 600.0;
+
+/// # Description
+/// whether a tab is still sending the submission it claimed at `claimed_at`
+/// ## Parameters
+/// - both are in milliseconds since the unix epoch, `claimed_at` is `None` when it wasn't claimed
+pub fn is_claim_held(claimed_at: Option<f64>, now: f64) -> bool {
+    claimed_at
+        .is_some_and(|claimed_at| now < claimed_at + QUEUE_CLAIM_EXPIRY_IN_SECS as f64 * 1000.0)
+}
+
+/// # Description
+/// what a single form field held
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum QueuedValue {
+    Text(String),
+    /// a recording (or any other file the form carried)
+    File {
+        file_name: String,
+        bytes: Vec<u8>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct QueuedField {
+    pub name: String,
+    pub value: QueuedValue,
+}
+
+/// # Description
+/// a form submission waiting to be sent again
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct QueuedSubmission {
+    pub id: String,
+    /// where the form gets posted to
+    pub url: String,
+    /// milliseconds since the unix epoch
+    pub queued_at: f64,
+    pub fields: Vec<QueuedField>,
+    /// failed attempts at sending it again
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    /// the server turned it down, it's only sent again when asked to
+    pub rejected: bool,
+}
+
+impl QueuedSubmission {
+    pub fn new(id: &str, url: &str, queued_at: f64, fields: Vec<QueuedField>) -> Self {
+        Self {
+            id: id.to_string(),
+            url: url.to_string(),
+            queued_at,
+            fields,
+            attempts: 0,
+            last_error: None,
+            rejected: false,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("queued submission serialization failed")
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, RecorderError> {
+        bincode::deserialize(bytes)
+            .map_err(|err| RecorderError::Storage(format!("bad queued submission: {}", err)))
+    }
+
+    /// # Description
+    /// the file fields of the form, recordings among them
+    pub fn files(&self) -> impl Iterator<Item = (&str, &str, &[u8])> {
+        self.fields.iter().filter_map(|field| match &field.value {
+            QueuedValue::File { file_name, bytes } => {
+                Some((field.name.as_str(), file_name.as_str(), bytes.as_slice()))
+            }
+            QueuedValue::Text(_) => None,
+        })
+    }
+
+    /// # Description
+    /// whether the submission gets sent again automatically
+    pub fn is_due(&self) -> bool {
+        !self.rejected
+    }
+
+    /// # Description
+    /// notes a failed attempt at sending the submission
+    /// ## Parameters
+    /// - `status` is the server's answer, `None` when it couldn't be reached
+    pub fn record_failure(&mut self, status: Option<u16>, message: &str) {
+        self.attempts += 1;
+        self.rejected = status.is_some_and(|status| !is_transient_status(status));
+        self.last_error = Some(message.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn submission() -> QueuedSubmission {
+        QueuedSubmission::new(
+            "17f-0001",
+            "/upload",
+            1_650_000_000_000.0,
+            vec![
+                QueuedField {
+                    name: "comment".to_string(),
+                    value: QueuedValue::Text("hello".to_string()),
+                },
+                QueuedField {
+                    name: "audio_recording".to_string(),
+                    value: QueuedValue::File {
+                        file_name: "rec.adhoc".to_string(),
+                        bytes: vec![0, 1, 2, 3],
+                    },
+                },
+            ],
+        )
+    }
+
+    #[test]
+    fn queued_submissions_round_trip_through_their_bytes() {
+        let queued = submission();
+        assert_eq!(
+            QueuedSubmission::from_bytes(&queued.to_bytes()).unwrap(),
+            queued
+        );
+        assert_eq!(
+            queued.files().collect::<Vec<_>>(),
+            vec![("audio_recording", "rec.adhoc", &[0u8, 1, 2, 3][..])]
+        );
+
+        let err = QueuedSubmission::from_bytes(&[1, 2, 3]).err().unwrap();
+        assert_eq!(err.kind(), "storage");
+    }
+
+    #[test]
+    fn only_rejected_submissions_wait_to_be_retried_by_hand() {
+        let mut queued = submission();
+        queued.record_failure(None, "the connection failed");
+        queued.record_failure(Some(503), "server answered with 503");
+        assert!(queued.is_due());
+        assert_eq!(queued.attempts, 2);
+
+        queued.record_failure(Some(422), "server answered with 422");
+        assert!(!queued.is_due());
+        assert_eq!(
+            queued.last_error.as_deref(),
+            Some("server answered with 422")
+        );
+    }
+
+    #[test]
+    fn claims_on_queued_submissions_run_out() {
+        assert!(!is_claim_held(None, 1_000.0));
+        assert!(is_claim_held(Some(1_000.0), 1_000.0));
+        let expiry = QUEUE_CLAIM_EXPIRY_IN_SECS as f64 * 1000.0;
+        assert!(is_claim_held(Some(1_000.0), 1_000.0 + expiry - 1.0));
+        assert!(!is_claim_held(Some(1_000.0), 1_000.0 + expiry));
+    }
+}
@@ -0,0 +1,503 @@
+use super::web::{object_store, open_database, request_result, AUTOSAVE_STORE, SEGMENT_STORE};
+use crate::{
+    recorder::{RecorderError, COMPRESSION_LEVEL},
+    upload::{Chunk, ChunkAssembler, SegmentEncoder},
+    web_utils,
+};
+use adhoc_audio::{AdhocCodec, StreamInfo};
+use js_sys::{Array, Object, Reflect, Uint8Array};
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    rc::{Rc, Weak},
+};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::spawn_local;
+use web_sys::{console, IdbDatabase, IdbKeyRange, IdbTransactionMode};
+
+/// at most this much of a recording is lost when the tab goes away mid-recording
+pub const AUTOSAVE_INTERVAL_IN_SECS: f32 = 2.0;
+/// how often the tab writing an autosave lets other tabs know it's still around
+pub const AUTOSAVE_HEARTBEAT_IN_SECS: f32 = 5.0;
+/// an autosave whose tab went quiet for this long was left behind
+pub const AUTOSAVE_ABANDONED_AFTER_IN_SECS: f32 = 15.0;
+
+/// # Description
+/// what's known about an autosaved recording
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AutosaveInfo {
+    pub id: u64,
+    /// `location.pathname` of the page the recording was made on
+    pub page: String,
+    /// form field of the recorder that made it
+    pub field_name: String,
+    pub file_name: String,
+    pub frequency: u32,
+    pub channels: u32,
+    /// milliseconds since the unix epoch
+    pub updated_at: f64,
+    /// segments stored so far
+    pub segments: u32,
+    /// milliseconds since the unix epoch, refreshed for as long as a tab holds the recording
+    pub heartbeat_at: f64,
+}
+
+impl AutosaveInfo {
+    pub fn stream_info(&self) -> StreamInfo {
+        StreamInfo::new(self.frequency, self.channels)
+    }
+
+    /// # Description
+    /// whether a tab (this one or another) is still writing the autosave
+    /// ## Parameters
+    /// - `now` is in milliseconds since the unix epoch
+    /// ## Comments
+    /// - a live autosave belongs to a recorder that's still around, it must not be recovered
+    pub fn is_live(&self, now: f64) -> bool {
+        self.millis_until_abandoned(now) > 0.0
+    }
+
+    /// # Description
+    /// how long until the autosave counts as left behind, unless its tab speaks up again
+    pub fn millis_until_abandoned(&self, now: f64) -> f64 {
+        (self.heartbeat_at + AUTOSAVE_ABANDONED_AFTER_IN_SECS as f64 * 1000.0 - now).max(0.0)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("autosave serialization failed")
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, RecorderError> {
+        bincode::deserialize(bytes)
+            .map_err(|err| RecorderError::Storage(format!("bad autosave: {}", err)))
+    }
+}
+
+/// # Description
+/// puts the stored segments of a recording back together
+/// ## Returns
+/// the number of segments that made it into the recording, along with the recording
+/// ## Comments
+/// - everything up to the first missing segment is recovered, a write that never made it only
+///   costs what was recorded after it
+/// - unlike a loaded recording the codec can be recorded into right away
+pub fn assemble_autosave<I>(segments: I) -> Result<(u32, AdhocCodec), RecorderError>
+where
+    I: IntoIterator<Item = (u32, Vec<u8>)>,
+{
+    let mut assembler = ChunkAssembler::new().with_compression_level(COMPRESSION_LEVEL);
+    for (sequence, bytes) in segments {
+        assembler.receive(sequence, bytes);
+    }
+    match assembler.next_sequence() {
+        0 => Err(RecorderError::Storage("nothing was autosaved".to_string())),
+        count => assembler
+            .assemble(count)
+            .map(|codec| (count, codec))
+            .map_err(|err| RecorderError::Storage(err.to_string())),
+    }
+}
+
+/// # Description
+/// `location.pathname` of the current page
+pub fn current_page() -> String {
+    web_sys::window()
+        .and_then(|window| window.location().pathname().ok())
+        .unwrap_or_default()
+}
+
+enum AutosaveOp {
+    /// stores a segment along with the updated info
+    Write(AutosaveInfo, Chunk),
+    /// forgets everything stored under an id
+    Clear(u64),
+    /// tells other tabs the autosave stored under an id is still being written
+    Heartbeat(u64),
+}
+
+#[derive(Default)]
+struct AutosaveOps {
+    pending: VecDeque<AutosaveOp>,
+    /// whether a task is currently working through `pending`
+    pumping: bool,
+}
+
+/// # Description
+/// Stores a recording in IndexedDB segment by segment while it's being recorded, so it survives
+/// the tab crashing or being closed
+/// ## Comments
+/// - segments are independently decodable adhoc streams, see `SegmentEncoder`
+/// - writes happen in order, one at a time, and finish even when the writer is dropped
+/// - nothing is stored before the first segment is full (or `flush`ed)
+/// - while the writer is around, the stored info gets a heartbeat that keeps other tabs from
+///   recovering the recording, see `AutosaveInfo::is_live`
+pub struct AutosaveWriter {
+    info: AutosaveInfo,
+    segments: SegmentEncoder,
+    ops: Rc<RefCell<AutosaveOps>>,
+}
+
+impl AutosaveWriter {
+    pub fn new(page: &str, field_name: &str, file_name: &str, info: StreamInfo) -> Self {
+        Self::resume(AutosaveInfo {
+            id: new_autosave_id(),
+            page: page.to_string(),
+            field_name: field_name.to_string(),
+            file_name: file_name.to_string(),
+            frequency: info.frequency() as u32,
+            channels: info.channels() as u32,
+            updated_at: 0.0,
+            segments: 0,
+            heartbeat_at: 0.0,
+        })
+    }
+
+    /// # Description
+    /// carries on writing a recovered autosave, new segments go after the ones in `info`
+    pub fn resume(info: AutosaveInfo) -> Self {
+        let segments = new_encoder(&info);
+        let ops = Rc::new(RefCell::new(AutosaveOps::default()));
+        spawn_local(heartbeat(info.id, Rc::downgrade(&ops)));
+        Self {
+            info,
+            segments,
+            ops,
+        }
+    }
+
+    pub fn id(&self) -> u64 {
+        self.info.id
+    }
+
+    pub fn info(&self) -> &AutosaveInfo {
+        &self.info
+    }
+
+    /// # Description
+    /// encodes interleaved `samples`, full segments get stored right away
+    pub fn encode(&mut self, samples: &[f32]) {
+        if let Some(chunk) = self.segments.encode(samples) {
+            self.write(chunk);
+        }
+    }
+
+    /// # Description
+    /// stores whatever has been encoded since the last segment
+    pub fn flush(&mut self) {
+        if let Some(chunk) = self.segments.flush() {
+            self.write(chunk);
+        }
+    }
+
+    /// # Description
+    /// forgets everything stored so far, later segments start over under the same id
+    pub fn clear(&mut self) {
+        let stored = self.info.segments > 0;
+        self.info.segments = 0;
+        self.segments = new_encoder(&self.info);
+        if stored {
+            self.push(AutosaveOp::Clear(self.info.id));
+        }
+    }
+
+    fn write(&mut self, chunk: Chunk) {
+        self.info.segments = chunk.sequence + 1;
+        self.info.updated_at = js_sys::Date::now();
+        self.info.heartbeat_at = self.info.updated_at;
+        self.push(AutosaveOp::Write(self.info.clone(), chunk));
+    }
+
+    fn push(&mut self, op: AutosaveOp) {
+        push_op(&self.ops, op);
+    }
+}
+
+fn push_op(ops: &Rc<RefCell<AutosaveOps>>, op: AutosaveOp) {
+    let mut pending = ops.borrow_mut();
+    pending.pending.push_back(op);
+    if !pending.pumping {
+        pending.pumping = true;
+        spawn_local(pump(ops.clone()));
+    }
+}
+
+/// # Description
+/// refreshes the heartbeat of the autosave stored under `id` until its writer is dropped
+/// ## Comments
+/// - the first beat goes out right away, so a recovered autosave is claimed before other tabs
+///   look at it again
+async fn heartbeat(id: u64, ops: Weak<RefCell<AutosaveOps>>) {
+    while let Some(ops) = ops.upgrade() {
+        push_op(&ops, AutosaveOp::Heartbeat(id));
+        drop(ops);
+        web_utils::sleep((AUTOSAVE_HEARTBEAT_IN_SECS * 1000.0) as i32).await;
+    }
+}
+
+fn new_encoder(info: &AutosaveInfo) -> SegmentEncoder {
+    SegmentEncoder::new(info.stream_info(), COMPRESSION_LEVEL)
+        .with_segment_duration(AUTOSAVE_INTERVAL_IN_SECS)
+        .with_first_sequence(info.segments)
+}
+
+fn new_autosave_id() -> u64 {
+    // ids end up as javascript numbers, so they have to fit into 53 bits
+    (js_sys::Math::random() * (1u64 << 53) as f64) as u64
+}
+
+/// # Description
+/// applies pending writes until there are none left
+/// ## Comments
+/// - autosaving is best effort, should the database be unavailable the writes are dropped
+async fn pump(ops: Rc<RefCell<AutosaveOps>>) {
+    let db = match open_database().await {
+        Ok(db) => Some(db),
+        Err(err) => {
+            console::error_1(&err.to_string().into());
+            None
+        }
+    };
+    loop {
+        let next_op = ops.borrow_mut().pending.pop_front();
+        let op = match next_op {
+            Some(op) => op,
+            None => {
+                ops.borrow_mut().pumping = false;
+                return;
+            }
+        };
+        if let Some(db) = db.as_ref() {
+            if let Err(err) = apply(db, op).await {
+                console::error_1(&err.to_string().into());
+            }
+        }
+    }
+}
+
+async fn apply(db: &IdbDatabase, op: AutosaveOp) -> Result<(), RecorderError> {
+    match op {
+        AutosaveOp::Write(info, chunk) => {
+            // the segment and the info that counts it are stored together or not at all
+            let stores = Array::of2(&AUTOSAVE_STORE.into(), &SEGMENT_STORE.into());
+            let transaction = db
+                .transaction_with_str_sequence_and_mode(&stores, IdbTransactionMode::Readwrite)
+                .map_err(RecorderError::storage)?;
+            let segment = Object::new();
+            Reflect::set(&segment, &"id".into(), &JsValue::from(info.id as f64))
+                .map_err(RecorderError::storage)?;
+            Reflect::set(&segment, &"sequence".into(), &JsValue::from(chunk.sequence))
+                .map_err(RecorderError::storage)?;
+            Reflect::set(
+                &segment,
+                &"data".into(),
+                &Uint8Array::from(&chunk.bytes[..]),
+            )
+            .map_err(RecorderError::storage)?;
+            transaction
+                .object_store(AUTOSAVE_STORE)
+                .and_then(|store| store.put(&info_record(&info)?))
+                .map_err(RecorderError::storage)?;
+            let request = transaction
+                .object_store(SEGMENT_STORE)
+                .and_then(|store| store.put(&segment))
+                .map_err(RecorderError::storage)?;
+            request_result(&request).await.map(|_| ())
+        }
+        AutosaveOp::Clear(id) => {
+            let request = object_store(db, SEGMENT_STORE, IdbTransactionMode::Readwrite)?
+                .delete(&segment_range(id)?)
+                .map_err(RecorderError::storage)?;
+            request_result(&request).await?;
+            let request = object_store(db, AUTOSAVE_STORE, IdbTransactionMode::Readwrite)?
+                .delete(&JsValue::from(id as f64))
+                .map_err(RecorderError::storage)?;
+            request_result(&request).await.map(|_| ())
+        }
+        AutosaveOp::Heartbeat(id) => {
+            // only this tab writes the autosave, so reading and writing it apart is fine
+            let request = object_store(db, AUTOSAVE_STORE, IdbTransactionMode::Readonly)?
+                .get(&JsValue::from(id as f64))
+                .map_err(RecorderError::storage)?;
+            let record = request_result(&request).await?;
+            // nothing is stored before the first segment, or anymore once it's cleared
+            let mut info = match record_data(&record) {
+                Some(data) => AutosaveInfo::from_bytes(&data)?,
+                None => return Ok(()),
+            };
+            info.heartbeat_at = js_sys::Date::now();
+            let request = object_store(db, AUTOSAVE_STORE, IdbTransactionMode::Readwrite)?
+                .put(&info_record(&info).map_err(RecorderError::storage)?)
+                .map_err(RecorderError::storage)?;
+            request_result(&request).await.map(|_| ())
+        }
+    }
+}
+
+fn info_record(info: &AutosaveInfo) -> Result<JsValue, JsValue> {
+    let record = Object::new();
+    Reflect::set(&record, &"id".into(), &JsValue::from(info.id as f64))?;
+    Reflect::set(
+        &record,
+        &"data".into(),
+        &Uint8Array::from(&info.to_bytes()[..]),
+    )?;
+    Ok(record.into())
+}
+
+/// # Description
+/// every segment key of the recording stored under `id`
+fn segment_range(id: u64) -> Result<JsValue, RecorderError> {
+    let id = JsValue::from(id as f64);
+    IdbKeyRange::bound(
+        &Array::of2(&id, &JsValue::from(0)),
+        &Array::of2(&id, &JsValue::from(f64::INFINITY)),
+    )
+    .map(JsValue::from)
+    .map_err(RecorderError::storage)
+}
+
+fn record_data(record: &JsValue) -> Option<Vec<u8>> {
+    Reflect::get(record, &"data".into())
+        .ok()
+        .and_then(|data| data.dyn_into::<Uint8Array>().ok())
+        .map(|data| data.to_vec())
+}
+
+/// # Description
+/// Recordings left behind in IndexedDB by `AutosaveWriter`s, usually by a tab that went away
+/// mid-recording
+pub struct Autosaves {
+    db: IdbDatabase,
+}
+
+impl Autosaves {
+    pub async fn open() -> Result<Self, RecorderError> {
+        Ok(Self {
+            db: open_database().await?,
+        })
+    }
+
+    /// # Description
+    /// every autosaved recording, most recent first
+    pub async fn list(&self) -> Result<Vec<AutosaveInfo>, RecorderError> {
+        let request = object_store(&self.db, AUTOSAVE_STORE, IdbTransactionMode::Readonly)?
+            .get_all()
+            .map_err(RecorderError::storage)?;
+        let records = Array::from(&request_result(&request).await?);
+        let mut autosaves = Vec::new();
+        for record in records.iter() {
+            match record_data(&record).map(|data| AutosaveInfo::from_bytes(&data)) {
+                Some(Ok(info)) => autosaves.push(info),
+                Some(Err(err)) => console::error_1(&err.to_string().into()),
+                None => console::error_1(&"autosave without data".into()),
+            }
+        }
+        autosaves.sort_by(|a, b| b.updated_at.total_cmp(&a.updated_at));
+        Ok(autosaves)
+    }
+
+    /// # Description
+    /// puts an autosaved recording back together, see `assemble_autosave`
+    /// ## Returns
+    /// the autosave along with the recording, `None` when nothing is stored under `id`
+    /// ## Comments
+    /// - the info only counts the segments that made it into the recording, so an
+    ///   `AutosaveWriter::resume`d autosave carries on right after them
+    pub async fn recover(
+        &self,
+        id: u64,
+    ) -> Result<Option<(AutosaveInfo, AdhocCodec)>, RecorderError> {
+        let request = object_store(&self.db, AUTOSAVE_STORE, IdbTransactionMode::Readonly)?
+            .get(&JsValue::from(id as f64))
+            .map_err(RecorderError::storage)?;
+        let record = request_result(&request).await?;
+        if record.is_undefined() {
+            return Ok(None);
+        }
+        let mut info = record_data(&record)
+            .ok_or_else(|| RecorderError::Storage("autosave without data".to_string()))
+            .and_then(|data| AutosaveInfo::from_bytes(&data))?;
+
+        let request = object_store(&self.db, SEGMENT_STORE, IdbTransactionMode::Readonly)?
+            .get_all_with_key(&segment_range(id)?)
+            .map_err(RecorderError::storage)?;
+        let segments = Array::from(&request_result(&request).await?)
+            .iter()
+            .filter_map(|segment| {
+                let sequence = Reflect::get(&segment, &"sequence".into()).ok()?.as_f64()?;
+                Some((sequence as u32, record_data(&segment)?))
+            })
+            .collect::<Vec<_>>();
+        let (segments, codec) = assemble_autosave(segments)?;
+        info.segments = segments;
+        Ok(Some((info, codec)))
+    }
+
+    /// # Description
+    /// drops an autosaved recording
+    pub async fn discard(&self, id: u64) -> Result<(), RecorderError> {
+        apply(&self.db, AutosaveOp::Clear(id)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use adhoc_audio::Streamable;
+
+    #[test]
+    fn autosaves_recover_up_to_the_first_missing_segment() {
+        let info = StreamInfo::new(8_000, 1);
+        let mut segments = SegmentEncoder::new(info, COMPRESSION_LEVEL).with_segment_duration(1.0);
+        let mut chunks = Vec::new();
+        for _ in 0..4 {
+            chunks.extend(segments.encode(&[0.25; 8_000]));
+        }
+        assert_eq!(chunks.len(), 4);
+
+        // the third write never made it
+        let stored = chunks
+            .into_iter()
+            .filter(|chunk| chunk.sequence != 2)
+            .map(|chunk| (chunk.sequence, chunk.bytes));
+        let (recovered, codec) = assemble_autosave(stored).expect("recovery failed");
+        assert_eq!(recovered, 2);
+
+        let mut bytes = Vec::new();
+        codec.save_to(&mut bytes).expect("serialization failed");
+        let mut codec = AdhocCodec::load(&bytes[..]).expect("bad recording");
+        let mut frames = 0;
+        let mut buffer = [0.0; 1024];
+        while let Some(n) = codec.decode(&mut buffer) {
+            frames += n;
+        }
+        assert_eq!(frames, 16_000);
+
+        assert!(assemble_autosave(Vec::new()).is_err());
+    }
+
+    #[test]
+    fn autosaves_are_left_behind_once_their_tab_goes_quiet() {
+        let info = AutosaveInfo {
+            id: 7,
+            page: "/".to_string(),
+            field_name: "audio_recording".to_string(),
+            file_name: "rec.adhoc".to_string(),
+            frequency: 8_000,
+            channels: 1,
+            updated_at: 1_000.0,
+            segments: 1,
+            heartbeat_at: 1_000.0,
+        };
+        assert!(info.is_live(1_000.0 + AUTOSAVE_HEARTBEAT_IN_SECS as f64 * 1000.0));
+        assert_eq!(info.millis_until_abandoned(6_000.0), 10_000.0);
+        assert!(!info.is_live(16_000.0));
+        assert_eq!(
+            AutosaveInfo::from_bytes(&info.to_bytes())
+                .unwrap()
+                .heartbeat_at,
+            1_000.0
+        );
+    }
+}
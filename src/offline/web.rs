@@ -0,0 +1,391 @@
+use super::{is_claim_held, QueuedField, QueuedSubmission, QueuedValue};
+use crate::{
+    recorder::RecorderError,
+    upload::new_session_id,
+    web_utils::{self, bytes_to_blob, closure_to_function},
+};
+use js_sys::{Array, Function, Object, Promise, Reflect, Uint8Array};
+use std::cell::Cell;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::{spawn_local, JsFuture};
+use web_sys::{
+    console, Event, File, FormData, IdbDatabase, IdbObjectStore, IdbObjectStoreParameters,
+    IdbRequest, IdbTransactionMode,
+};
+
+const DATABASE_NAME: &str = "audio_recorder";
+const DATABASE_VERSION: u32 = 2;
+const QUEUE_STORE: &str = "queued_submissions";
+/// field of a queued submission's record noting when a tab claimed it
+const CLAIMED_AT: &str = "claimed_at";
+/// one `AutosaveInfo` per autosaved recording
+pub(super) const AUTOSAVE_STORE: &str = "autosaves";
+/// the segments of autosaved recordings, keyed by `[id, sequence]`
+pub(super) const SEGMENT_STORE: &str = "autosave_segments";
+
+thread_local! {
+    /// whether `retry_all` is already working through the queue
+    static RETRYING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// # Description
+/// Form submissions waiting in IndexedDB for the server to be reachable again
+/// ## Comments
+/// - every submission is stored as `{ id, data }`, `data` holding a serialized `QueuedSubmission`
+/// - a tab sending a submission claims it first, by noting `claimed_at` on its record, so other
+///   tabs leave it alone, see `is_claim_held`
+/// - submissions leave the queue once the server accepted them, or when they're discarded
+pub struct OfflineQueue {
+    db: IdbDatabase,
+}
+
+impl OfflineQueue {
+    /// # Description
+    /// opens the queue, creating it the first time around
+    pub async fn open() -> Result<Self, RecorderError> {
+        Ok(Self {
+            db: open_database().await?,
+        })
+    }
+
+    /// # Description
+    /// stores `submission`, replacing whatever was queued under the same id
+    /// ## Comments
+    /// - a claim on the submission is let go of
+    pub async fn push(&self, submission: &QueuedSubmission) -> Result<(), RecorderError> {
+        let record = Object::new();
+        Reflect::set(&record, &"id".into(), &JsValue::from_str(&submission.id))
+            .map_err(RecorderError::storage)?;
+        Reflect::set(
+            &record,
+            &"data".into(),
+            &Uint8Array::from(&submission.to_bytes()[..]),
+        )
+        .map_err(RecorderError::storage)?;
+        let request = self
+            .store(IdbTransactionMode::Readwrite)?
+            .put(&record)
+            .map_err(RecorderError::storage)?;
+        request_result(&request).await.map(|_| ())
+    }
+
+    /// # Description
+    /// queues everything `form_data` holds, files included
+    /// ## Parameters
+    /// - `url` is where the form gets posted to once it's retried
+    pub async fn queue_form(
+        &self,
+        url: &str,
+        form_data: &FormData,
+    ) -> Result<QueuedSubmission, RecorderError> {
+        let submission = QueuedSubmission::new(
+            &new_session_id(),
+            url,
+            js_sys::Date::now(),
+            form_fields(form_data).await?,
+        );
+        self.push(&submission).await?;
+        Ok(submission)
+    }
+
+    /// # Description
+    /// every queued submission, oldest first
+    /// ## Comments
+    /// - entries that can't be read are skipped, they can still be discarded by id
+    pub async fn list(&self) -> Result<Vec<QueuedSubmission>, RecorderError> {
+        let request = self
+            .store(IdbTransactionMode::Readonly)?
+            .get_all()
+            .map_err(RecorderError::storage)?;
+        let records = Array::from(&request_result(&request).await?);
+        let mut queued = Vec::new();
+        for record in records.iter() {
+            match read_record(&record) {
+                Ok(submission) => queued.push(submission),
+                Err(err) => console::error_1(&err.to_string().into()),
+            }
+        }
+        queued.sort_by(|a, b| a.queued_at.total_cmp(&b.queued_at));
+        Ok(queued)
+    }
+
+    pub async fn get(&self, id: &str) -> Result<Option<QueuedSubmission>, RecorderError> {
+        let request = self
+            .store(IdbTransactionMode::Readonly)?
+            .get(&JsValue::from_str(id))
+            .map_err(RecorderError::storage)?;
+        let record = request_result(&request).await?;
+        if record.is_undefined() {
+            Ok(None)
+        } else {
+            read_record(&record).map(Some)
+        }
+    }
+
+    /// # Description
+    /// drops a queued submission, recordings and all
+    pub async fn discard(&self, id: &str) -> Result<(), RecorderError> {
+        let request = self
+            .store(IdbTransactionMode::Readwrite)?
+            .delete(&JsValue::from_str(id))
+            .map_err(RecorderError::storage)?;
+        request_result(&request).await.map(|_| ())
+    }
+
+    /// # Description
+    /// claims a queued submission for this tab, so no other tab sends it as well
+    /// ## Returns
+    /// the submission, `None` when nothing is queued under `id` or another tab holds it
+    /// ## Comments
+    /// - the claim is checked and written in the same transaction, two tabs can't both get it
+    pub async fn claim(&self, id: &str) -> Result<Option<QueuedSubmission>, RecorderError> {
+        let store = self.store(IdbTransactionMode::Readwrite)?;
+        let request = store
+            .get(&JsValue::from_str(id))
+            .map_err(RecorderError::storage)?;
+        let record = request_result(&request).await?;
+        if record.is_undefined() {
+            return Ok(None);
+        }
+        let now = js_sys::Date::now();
+        let claimed_at = Reflect::get(&record, &CLAIMED_AT.into())
+            .ok()
+            .and_then(|claimed_at| claimed_at.as_f64());
+        if is_claim_held(claimed_at, now) {
+            return Ok(None);
+        }
+        let submission = read_record(&record)?;
+        Reflect::set(&record, &CLAIMED_AT.into(), &JsValue::from(now))
+            .map_err(RecorderError::storage)?;
+        let request = store.put(&record).map_err(RecorderError::storage)?;
+        request_result(&request).await?;
+        Ok(Some(submission))
+    }
+
+    /// # Description
+    /// sends a queued submission right away, whether it's due or not
+    /// ## Returns
+    /// the status and body of the server's answer
+    /// ## Comments
+    /// - the submission leaves the queue once the server accepted it
+    /// - a failed attempt is noted on the submission, see `QueuedSubmission::record_failure`
+    /// - a submission another tab is sending can't be retried until it's done with it
+    pub async fn retry(&self, id: &str) -> Result<(u16, String), RecorderError> {
+        let submission = self.claim(id).await?.ok_or_else(|| {
+            RecorderError::Storage(format!(
+                "nothing is queued as {}, or another tab is sending it",
+                id
+            ))
+        })?;
+        self.send(submission).await
+    }
+
+    /// # Description
+    /// sends a submission this tab claimed
+    async fn send(&self, mut submission: QueuedSubmission) -> Result<(u16, String), RecorderError> {
+        let id = submission.id.clone();
+        let form_data = to_form_data(&submission.fields)?;
+        let (status, err) =
+            match web_utils::send_form_data(&submission.url, &form_data, |_, _| {}).await {
+                Ok((status, response)) if (200..300).contains(&status) => {
+                    self.discard(&id).await?;
+                    return Ok((status, response));
+                }
+                Ok((status, response)) => (
+                    Some(status),
+                    RecorderError::Upload(format!("server answered with {}: {}", status, response)),
+                ),
+                Err(err) => (None, RecorderError::upload(err)),
+            };
+        submission.record_failure(status, &err.to_string());
+        self.push(&submission).await?;
+        Err(err)
+    }
+
+    /// # Description
+    /// sends every submission that's due, one after the other
+    /// ## Returns
+    /// how many of them the server accepted
+    /// ## Comments
+    /// - does nothing while another `retry_all` is still running
+    /// - submissions another tab is sending are skipped, see `claim`
+    pub async fn retry_all(&self) -> Result<usize, RecorderError> {
+        if RETRYING.with(|retrying| retrying.replace(true)) {
+            return Ok(0);
+        }
+        let sent = self.retry_due().await;
+        RETRYING.with(|retrying| retrying.set(false));
+        sent
+    }
+
+    async fn retry_due(&self) -> Result<usize, RecorderError> {
+        let mut sent = 0;
+        for submission in self.list().await? {
+            if !submission.is_due() {
+                continue;
+            }
+            // the queue is shared with every other tab, one of them may have got to it first
+            let submission = match self.claim(&submission.id).await? {
+                Some(submission) if submission.is_due() => submission,
+                _ => continue,
+            };
+            match self.send(submission).await {
+                Ok(_) => sent += 1,
+                // the failure is on the submission now, carry on with the others
+                Err(RecorderError::Upload(_)) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(sent)
+    }
+
+    fn store(&self, mode: IdbTransactionMode) -> Result<IdbObjectStore, RecorderError> {
+        object_store(&self.db, QUEUE_STORE, mode)
+    }
+}
+
+/// # Description
+/// opens the database the offline queue and the autosaves live in, its stores are created (or
+/// added to an older version of it) as needed
+pub(super) async fn open_database() -> Result<IdbDatabase, RecorderError> {
+    let factory = web_sys::window()
+        .ok_or_else(|| RecorderError::Dom("no window".to_string()))?
+        .indexed_db()
+        .map_err(RecorderError::storage)?
+        .ok_or_else(|| RecorderError::Storage("indexeddb is unavailable".to_string()))?;
+    let request = factory
+        .open_with_u32(DATABASE_NAME, DATABASE_VERSION)
+        .map_err(RecorderError::storage)?;
+
+    let upgrade_request = request.clone();
+    request.set_onupgradeneeded(Some(&closure_to_function(move |_: Event| {
+        let db = match upgrade_request
+            .result()
+            .and_then(|db| db.dyn_into::<IdbDatabase>())
+        {
+            Ok(db) => db,
+            Err(_) => return,
+        };
+        let segment_key = Array::of2(&"id".into(), &"sequence".into());
+        let stores = [
+            (QUEUE_STORE, JsValue::from_str("id")),
+            (AUTOSAVE_STORE, JsValue::from_str("id")),
+            (SEGMENT_STORE, segment_key.into()),
+        ];
+        for (name, key_path) in stores {
+            if !db.object_store_names().contains(name) {
+                let parameters = IdbObjectStoreParameters::new();
+                parameters.set_key_path(&key_path);
+                db.create_object_store_with_optional_parameters(name, &parameters)
+                    .ok();
+            }
+        }
+    })));
+
+    request_result(&request)
+        .await?
+        .dyn_into::<IdbDatabase>()
+        .map_err(RecorderError::storage)
+}
+
+/// # Description
+/// `name` in a transaction of its own
+pub(super) fn object_store(
+    db: &IdbDatabase,
+    name: &str,
+    mode: IdbTransactionMode,
+) -> Result<IdbObjectStore, RecorderError> {
+    db.transaction_with_str_and_mode(name, mode)
+        .and_then(|transaction| transaction.object_store(name))
+        .map_err(RecorderError::storage)
+}
+
+/// # Description
+/// retries whatever is queued now, and again every time the browser comes back online
+/// ## Returns
+/// the window's `online` listener, removing it stops the retries
+pub fn retry_when_online() -> Result<Function, RecorderError> {
+    let window = web_sys::window().ok_or_else(|| RecorderError::Dom("no window".to_string()))?;
+    let on_online = closure_to_function(|_: Event| spawn_local(retry_queued()));
+    window
+        .add_event_listener_with_callback("online", &on_online)
+        .map_err(RecorderError::dom)?;
+    spawn_local(retry_queued());
+    Ok(on_online)
+}
+
+async fn retry_queued() {
+    let sent = match OfflineQueue::open().await {
+        Ok(queue) => queue.retry_all().await,
+        Err(err) => Err(err),
+    };
+    match sent {
+        Ok(0) => {}
+        Ok(sent) => console::log_1(&format!("sent {} queued submission(s)", sent).into()),
+        Err(err) => console::error_1(&err.to_string().into()),
+    }
+}
+
+/// # Description
+/// reads every field of `form_data`, files included
+pub async fn form_fields(form_data: &FormData) -> Result<Vec<QueuedField>, RecorderError> {
+    let mut fields = Vec::new();
+    for entry in form_data.entries() {
+        let entry = Array::from(&entry.map_err(RecorderError::dom)?);
+        let name = entry.get(0).as_string().unwrap_or_default();
+        let value = match entry.get(1).dyn_into::<File>() {
+            Ok(file) => {
+                let buffer = JsFuture::from(file.array_buffer())
+                    .await
+                    .map_err(RecorderError::storage)?;
+                QueuedValue::File {
+                    file_name: file.name(),
+                    bytes: Uint8Array::new(&buffer).to_vec(),
+                }
+            }
+            Err(value) => QueuedValue::Text(value.as_string().unwrap_or_default()),
+        };
+        fields.push(QueuedField { name, value });
+    }
+    Ok(fields)
+}
+
+fn to_form_data(fields: &[QueuedField]) -> Result<FormData, RecorderError> {
+    let form_data = FormData::new().map_err(RecorderError::dom)?;
+    for field in fields {
+        match &field.value {
+            QueuedValue::Text(text) => form_data.append_with_str(&field.name, text),
+            QueuedValue::File { file_name, bytes } => form_data.append_with_blob_and_filename(
+                &field.name,
+                &bytes_to_blob(bytes),
+                file_name,
+            ),
+        }
+        .map_err(RecorderError::dom)?;
+    }
+    Ok(form_data)
+}
+
+fn read_record(record: &JsValue) -> Result<QueuedSubmission, RecorderError> {
+    let data = Reflect::get(record, &"data".into())
+        .ok()
+        .and_then(|data| data.dyn_into::<Uint8Array>().ok())
+        .ok_or_else(|| RecorderError::Storage("queued submission without data".to_string()))?;
+    QueuedSubmission::from_bytes(&data.to_vec())
+}
+
+/// # Description
+/// waits for an IndexedDB request to succeed
+pub(super) async fn request_result(request: &IdbRequest) -> Result<JsValue, RecorderError> {
+    let done = Promise::new(&mut |resolve, reject| {
+        request.set_onsuccess(Some(&resolve));
+        request.set_onerror(Some(&reject));
+    });
+    if JsFuture::from(done).await.is_err() {
+        return Err(match request.error() {
+            Ok(Some(err)) => RecorderError::storage(err.into()),
+            _ => RecorderError::Storage("request failed".to_string()),
+        });
+    }
+    request.result().map_err(RecorderError::storage)
+}
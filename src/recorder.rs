@@ -0,0 +1,2562 @@
+mod constraints;
+mod devices;
+mod error;
+mod preview;
+
+pub use constraints::*;
+pub use devices::*;
+pub use error::*;
+pub use preview::*;
+
+use crate::{
+    capture::{
+        self, register_recorder_worklet, BlockBuffer, CaptureBackend, CaptureConfig, RecordGate,
+        RecordStatus, VoiceActivation, VoiceEvent, VoiceGate, WebCaptureSource, CAPTURE_BLOCK_SIZE,
+    },
+    collections::{LinkedList, LinkedListIterator, Ptr},
+    math,
+    offline::{self, current_page, AutosaveInfo, AutosaveWriter, Autosaves, OfflineQueue},
+    recording::{decode_planar, transcode_to_wav, trim_recording, TrimConfig},
+    upload::{
+        is_transient_status, recording_id_field, retry_delay_in_millis, ChunkUploader,
+        UploadProgress,
+    },
+    web_utils::{
+        self, bytes_to_blob, bytes_to_blob_with_type, closure_to_function, download_blob, DomIter,
+        NodeIter, ParentIter,
+    },
+};
+use adhoc_audio::{AdhocCodec, StreamInfo};
+use js_sys::{Function, Object, Reflect};
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::RefCell,
+    fmt,
+    future::Future,
+    io::Cursor,
+    rc::{Rc, Weak},
+};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::spawn_local;
+use web_sys::{
+    console, AudioBufferSourceNode, AudioContext, CustomEvent, CustomEventInit, Element, Event,
+    FocusEvent, FormData, HtmlButtonElement, HtmlFormElement, HtmlInputElement, HtmlOptionElement,
+    HtmlSelectElement, MediaStream, MediaStreamTrack, MediaStreamTrackState, MouseEvent,
+    PermissionStatus,
+};
+
+const RAMP_UP_DURATION_IN_SECS: f32 = 0.1;
+const BEEP_DURATION_IN_SECS: f32 = 0.2;
+const BEEP_DAMPEN_DELTA: f32 = 0.1;
+/// compression level recordings are encoded at
+pub const COMPRESSION_LEVEL: u32 = 4;
+const DEFAULT_FIELD_NAME: &str = "audio_recording";
+pub const WAV_MIME_TYPE: &str = "audio/wav";
+/// name of the event dispatched on a form once the server accepted it
+pub const SUBMIT_EVENT: &str = "recordersubmit";
+/// name of the event dispatched on a form while it's being uploaded
+pub const PROGRESS_EVENT: &str = "recorderprogress";
+/// name of the event dispatched on a form that was kept for later because the server was out of reach
+pub const QUEUED_EVENT: &str = "recorderqueued";
+/// name of the event dispatched on a record button that got back an autosaved recording
+pub const RECOVERED_EVENT: &str = "recorderrecovered";
+/// css property of a form holding how much of it has been uploaded
+const PROGRESS_PROPERTY: &str = "--upload-progress";
+/// set on a form while (and after) it's being submitted
+const UPLOAD_STATE_ATTRIBUTE: &str = "data-upload-state";
+/// attempts at posting a form before giving up
+const MAX_SUBMIT_ATTEMPTS: u32 = 4;
+/// how often the playback controls of a recorder are refreshed while playing
+const PLAYBACK_REFRESH_IN_MILLIS: i32 = 100;
+/// name of the event dispatched when a recorder fails
+pub const ERROR_EVENT: &str = "recordererror";
+/// how long the microphone stays open once nothing is being recorded anymore
+pub const IDLE_RELEASE_IN_SECS: f64 = 10.0;
+/// set on the document element, it tells whether the page may record from the microphone
+const PERMISSION_ATTRIBUTE: &str = "data-microphone";
+
+/// # Description
+/// what gets stored in a record button's `data-state` attribute
+#[derive(Serialize, Deserialize, Copy, Clone)]
+pub struct RecordState {
+    status: RecordStatus,
+    processing_node: Ptr,
+    /// id the recording is autosaved under, it names the session that can be recovered should the
+    /// page go away mid-recording
+    autosave: Option<u64>,
+}
+impl fmt::Display for RecordState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state_binary = bincode::serialize(self).expect("state serialization failed(binary)");
+        write!(f, "{}", base64::encode(state_binary))
+    }
+}
+impl RecordState {
+    pub fn from_string(base64: &str) -> Result<Self, RecorderError> {
+        let binary = base64::decode(base64)
+            .map_err(|err| RecorderError::MalformedState(format!("bad base64: {}", err)))?;
+        bincode::deserialize::<RecordState>(&binary)
+            .map_err(|err| RecorderError::MalformedState(format!("bad binary: {}", err)))
+    }
+
+    pub fn autosave(&self) -> Option<u64> {
+        self.autosave
+    }
+}
+
+/// called with the peak amplitude of every captured block while recording
+pub type LevelListener = Rc<dyn Fn(f32)>;
+/// called every time a session changes its `RecordStatus`
+pub type StatusListener = Rc<dyn Fn(RecordStatus)>;
+/// called with whether someone is speaking, while a voice activated session records
+pub type VoiceListener = Rc<dyn Fn(bool)>;
+/// called while a form and its recordings are being uploaded
+pub type ProgressListener = Rc<dyn Fn(&HtmlFormElement, UploadProgress)>;
+
+/// # Description
+/// how a new recorder session should record
+#[derive(Clone)]
+pub struct SessionConfig {
+    /// `None` records at the `AudioContext` rate
+    pub sample_rate: Option<u32>,
+    pub channels: u32,
+    /// form field the recording is uploaded as
+    pub field_name: String,
+    pub file_name: String,
+    /// play a beep when recording starts and stops
+    pub beep: bool,
+    /// upload the recording in segments to this url while it's being recorded
+    pub chunk_url: Option<String>,
+    /// keep the recording in browser storage while it's being recorded, see `AutosaveWriter`,
+    /// off unless asked for
+    pub autosave: bool,
+    /// only record while someone speaks, see `VoiceGate`
+    pub voice: Option<VoiceActivation>,
+    /// trim the silence out of the recording before it's handed out, see `trim_silence`
+    pub trim: Option<TrimConfig>,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            sample_rate: None,
+            channels: 1,
+            field_name: DEFAULT_FIELD_NAME.to_string(),
+            file_name: "rec.adhoc".to_string(),
+            beep: true,
+            chunk_url: None,
+            autosave: false,
+            voice: None,
+            trim: None,
+        }
+    }
+}
+
+impl SessionConfig {
+    /// # Description
+    /// reads the session config off the `data-*` attributes of the recorder `button` belongs to
+    pub fn for_button(button: &HtmlButtonElement) -> Self {
+        let (field_name, file_name) = recorder_field_name(button);
+        Self {
+            sample_rate: recorder_sample_rate(button),
+            channels: recorder_channels(button),
+            field_name,
+            file_name,
+            beep: true,
+            chunk_url: recorder_chunk_url(button),
+            autosave: recorder_autosave(button),
+            voice: recorder_voice_activation(button),
+            trim: recorder_trim_silence(button),
+        }
+    }
+
+    pub fn with_sample_rate(mut self, sample_rate: Option<u32>) -> Self {
+        self.sample_rate = sample_rate;
+        self
+    }
+
+    pub fn with_channels(mut self, channels: u32) -> Self {
+        self.channels = channels.max(1);
+        self
+    }
+
+    /// # Description
+    /// uploads the recording as `<name>.adhoc` under the `name` field
+    pub fn with_field_name(mut self, name: &str) -> Self {
+        self.file_name = format!("{}.adhoc", name);
+        self.field_name = name.to_string();
+        self
+    }
+
+    pub fn with_beep(mut self, beep: bool) -> Self {
+        self.beep = beep;
+        self
+    }
+
+    pub fn with_chunk_url(mut self, chunk_url: Option<String>) -> Self {
+        self.chunk_url = chunk_url;
+        self
+    }
+
+    pub fn with_autosave(mut self, autosave: bool) -> Self {
+        self.autosave = autosave;
+        self
+    }
+
+    pub fn with_voice_activation(mut self, voice: Option<VoiceActivation>) -> Self {
+        self.voice = voice;
+        self
+    }
+
+    pub fn with_trim_silence(mut self, trim: Option<TrimConfig>) -> Self {
+        self.trim = trim;
+        self
+    }
+}
+
+/// # Description
+/// everything a single recorder widget owns
+/// ## Comments
+/// - every `.recorder_button` gets its own session so several recorders can live in the same form
+/// - sessions created through the javascript api aren't bound to any button
+pub struct RecorderSession {
+    pub codec: AdhocCodec,
+    pub info: StreamInfo,
+    pub status: RecordStatus,
+    /// form field the recording is uploaded as
+    pub field_name: String,
+    pub file_name: String,
+    pub beep: bool,
+    /// `None` while the recorder is stopped
+    pub capture: Option<WebCaptureSource>,
+    /// set when the recording is uploaded in segments while recording
+    pub uploader: Option<ChunkUploader>,
+    pub level_listener: Option<LevelListener>,
+    pub status_listener: Option<StatusListener>,
+    /// the decoded recording, kept around until the recording changes
+    pub preview: Option<Preview>,
+    /// set when the recording is kept in browser storage while recording
+    pub autosave: Option<AutosaveWriter>,
+    /// set when only speech gets recorded
+    pub voice: Option<VoiceGate>,
+    pub voice_listener: Option<VoiceListener>,
+    /// set when the silence gets trimmed out of the recording before it's handed out
+    pub trim: Option<TrimConfig>,
+    /// fades the blocks of the running capture around pauses
+    gate: RecordGate,
+    /// samples the running capture still skips, the start beep is in them
+    warmup_samples: usize,
+}
+
+impl RecorderSession {
+    /// # Description
+    /// stops capturing but keeps whatever has been recorded so far
+    pub fn stop(&mut self) {
+        self.end_capture();
+        // the next take waits for speech again, silence still held back is trailing silence
+        if let Some(voice) = self.voice.as_mut() {
+            voice.reset();
+        }
+        if let Some(autosave) = self.autosave.as_mut() {
+            autosave.flush();
+        }
+        self.status = RecordStatus::Stopped;
+    }
+
+    /// # Description
+    /// tears the capture down, the end of the take it still holds back is recorded first
+    fn end_capture(&mut self) {
+        if let Some(capture) = self.capture.take() {
+            capture.finish(|microphone| {
+                // the voice gate holds back a short block of its own
+                if let Some(voice) = self.voice.as_mut() {
+                    voice.released().flush();
+                }
+                self.record_captured(microphone);
+            });
+        }
+    }
+
+    /// # Description
+    /// encodes the blocks the capture has ready, along with the upload and autosave
+    /// ## Returns
+    /// the peak amplitude of what was recorded and the latest event of the voice gate
+    fn record_captured(
+        &mut self,
+        microphone: &mut BlockBuffer,
+    ) -> (Option<f32>, Option<VoiceEvent>) {
+        self.warmup_samples -= microphone.discard(self.warmup_samples);
+        if self.warmup_samples > 0 {
+            return (None, None);
+        }
+
+        let mut amplitude = None;
+        let mut voice_event = None;
+        let status = self.status;
+        let voice_activated = self.voice.is_some();
+        let gate = &mut self.gate;
+        let uploader = &mut self.uploader;
+        let autosave = &mut self.autosave;
+        // a voice activated recording only encodes what makes it through its gate
+        let source = match (status, self.voice.as_mut()) {
+            (RecordStatus::Recording, Some(voice)) => {
+                (amplitude, voice_event) = voice.feed(microphone);
+                voice.released()
+            }
+            // the block fading a pause out goes through it as well, so silence it held
+            // back stays out, the paused audio after that block never gets near it
+            (RecordStatus::Paused, Some(voice)) => {
+                if gate.is_open() {
+                    voice.feed(microphone);
+                }
+                microphone.discard(microphone.pending());
+                voice.released()
+            }
+            _ => microphone,
+        };
+        capture::record(source, &mut self.codec, |microphone_samples| {
+            if status == RecordStatus::Recording && !voice_activated {
+                amplitude = math::peak_amplitude(microphone_samples);
+            }
+            let keep = gate.apply(status, microphone_samples);
+            if let (true, Some(uploader)) = (keep, uploader.as_mut()) {
+                uploader.encode(microphone_samples);
+            }
+            if let (true, Some(autosave)) = (keep, autosave.as_mut()) {
+                autosave.encode(microphone_samples);
+            }
+            keep
+        });
+        // the pause had its one block to fade out, when the gate held it back there's
+        // nothing to fade and later paused blocks mustn't reach the gate
+        if status == RecordStatus::Paused && voice_activated {
+            gate.close();
+        }
+        (amplitude, voice_event)
+    }
+
+    /// # Description
+    /// stops capturing and hands out the encoded recording
+    /// ## Comments
+    /// - the session starts over with an empty stream afterwards
+    pub fn take_recording(&mut self) -> Result<Vec<u8>, RecorderError> {
+        self.stop();
+        let compressed_audio = self.snapshot()?;
+        self.codec = new_codec(self.info);
+        self.preview = None;
+        self.clear_autosave();
+        Ok(compressed_audio)
+    }
+
+    /// # Description
+    /// stops capturing and throws away what has been recorded, a chunked upload starts over
+    /// ## Comments
+    /// - a fresh codec is swapped in, `AdhocCodec::init` only rewinds the stream and a save
+    ///   made before the next encode would still hold the discarded take
+    pub fn discard(&mut self) {
+        self.stop();
+        self.codec = new_codec(self.info);
+        self.preview = None;
+        self.clear_autosave();
+        self.restart_upload();
+    }
+
+    /// # Description
+    /// the encoded recording so far, the session keeps recording
+    pub fn snapshot(&self) -> Result<Vec<u8>, RecorderError> {
+        let mut compressed_audio = Vec::<u8>::new();
+        self.codec
+            .save_to(&mut compressed_audio)
+            .ok_or_else(|| RecorderError::Codec("recording failed to serialize".to_string()))?;
+        Ok(compressed_audio)
+    }
+
+    /// # Description
+    /// the encoded recording as it gets uploaded, the session keeps recording
+    /// ## Comments
+    /// - unlike `snapshot` its silence is trimmed when the session asks for it, which means
+    ///   decoding and encoding it again
+    pub fn recording(&self) -> Result<Vec<u8>, RecorderError> {
+        let compressed_audio = self.snapshot()?;
+        match self.trim {
+            Some(trim) => {
+                let mut trimmed = Vec::new();
+                trim_recording(
+                    &compressed_audio[..],
+                    &mut trimmed,
+                    &trim,
+                    COMPRESSION_LEVEL,
+                )
+                .map_err(|err| RecorderError::Codec(err.to_string()))?;
+                Ok(trimmed)
+            }
+            None => Ok(compressed_audio),
+        }
+    }
+
+    /// # Description
+    /// decodes the recording so far into a 16 bit wav file, the session keeps recording
+    /// ## Comments
+    /// - its silence is trimmed like the `recording`'s
+    pub fn export_wav(&self) -> Result<Vec<u8>, RecorderError> {
+        let mut wav = Cursor::new(Vec::new());
+        transcode_to_wav(&self.recording()?[..], &mut wav)
+            .map_err(|err| RecorderError::Codec(err.to_string()))?;
+        Ok(wav.into_inner())
+    }
+
+    /// # Description
+    /// `file_name` with a `.wav` extension
+    pub fn wav_file_name(&self) -> String {
+        let stem = self
+            .file_name
+            .rsplit_once('.')
+            .map_or(self.file_name.as_str(), |(stem, _)| stem);
+        format!("{}.wav", stem)
+    }
+
+    /// # Description
+    /// stops capturing and hands out the chunked upload of the recording
+    /// ## Comments
+    /// - the session starts over with an empty stream and a new upload afterwards
+    pub fn take_upload(&mut self) -> Option<ChunkUploader> {
+        self.stop();
+        self.codec = new_codec(self.info);
+        self.preview = None;
+        self.clear_autosave();
+        self.restart_upload()
+    }
+
+    /// # Description
+    /// drops the autosaved copy of the recording, the recording is taken care of
+    pub fn clear_autosave(&mut self) {
+        if let Some(autosave) = self.autosave.as_mut() {
+            autosave.clear();
+        }
+    }
+
+    /// # Description
+    /// swaps in a new upload session against the same url
+    /// ## Returns
+    /// the previous upload
+    fn restart_upload(&mut self) -> Option<ChunkUploader> {
+        let uploader = self.uploader.take()?;
+        self.uploader = Some(ChunkUploader::new(
+            uploader.url(),
+            self.info,
+            COMPRESSION_LEVEL,
+        ));
+        Some(uploader)
+    }
+}
+
+fn new_voice_gate(voice: VoiceActivation, info: StreamInfo) -> VoiceGate {
+    VoiceGate::new(
+        voice,
+        CAPTURE_BLOCK_SIZE as usize,
+        info.frequency() as u32,
+        info.channels(),
+    )
+}
+
+fn new_codec(info: StreamInfo) -> AdhocCodec {
+    AdhocCodec::new()
+        .with_compression_level(COMPRESSION_LEVEL)
+        .with_info(info)
+}
+
+struct RecorderState {
+    ctx: AudioContext,
+    /// `None` until something gets recorded, and again once the microphone sat idle for a while
+    stream: Option<MediaStream>,
+    /// microphone `stream` comes from (or will come from), `None` for the browser's default
+    input_device: Option<String>,
+    /// what `stream` was opened with
+    constraints: CaptureConstraints,
+    /// seconds the microphone stays open while nothing is being recorded, `None` keeps it open
+    idle_release: Option<f64>,
+    /// bumped whenever the microphone goes idle, a pending release only happens when it's still
+    /// the latest one
+    idle_generation: u32,
+    /// the microphone's entry in the permissions api, mirrored onto the page
+    permission_status: Option<PermissionStatus>,
+    capture_backend: CaptureBackend,
+    /// recorder sessions, a session is keyed by its `Ptr` which is stored in the button's `data-state`
+    processor_list: LinkedList<RecorderSession>,
+    progress_listener: Option<ProgressListener>,
+}
+
+impl RecorderState {
+    fn session_mut(&mut self, session: Ptr) -> Result<&mut RecorderSession, RecorderError> {
+        self.processor_list
+            .get_mut(session)
+            .and_then(|node| node.data_mut())
+            .ok_or_else(unknown_session)
+    }
+
+    fn create_session(&mut self, config: SessionConfig) -> Ptr {
+        let ctx_rate = self.ctx.sample_rate() as u32;
+        let info = StreamInfo::new(config.sample_rate.unwrap_or(ctx_rate), config.channels);
+        let autosave = config.autosave.then(|| {
+            AutosaveWriter::new(&current_page(), &config.field_name, &config.file_name, info)
+        });
+        self.processor_list.push_front(RecorderSession {
+            codec: new_codec(info),
+            info,
+            status: RecordStatus::Stopped,
+            field_name: config.field_name,
+            file_name: config.file_name,
+            beep: config.beep,
+            capture: None,
+            uploader: config
+                .chunk_url
+                .map(|url| ChunkUploader::new(&url, info, COMPRESSION_LEVEL)),
+            level_listener: None,
+            status_listener: None,
+            preview: None,
+            autosave,
+            voice: config.voice.map(|voice| new_voice_gate(voice, info)),
+            voice_listener: None,
+            trim: config.trim,
+            gate: RecordGate::new(info.channels()),
+            warmup_samples: 0,
+        });
+        self.processor_list.front()
+    }
+
+    /// # Description
+    /// creates a stopped session holding a recovered recording, it keeps autosaving right after
+    /// what was recovered
+    /// ## Comments
+    /// - the recording isn't uploaded in segments, the recovered part was never sent
+    fn restore_session(
+        &mut self,
+        config: SessionConfig,
+        autosave: AutosaveInfo,
+        codec: AdhocCodec,
+    ) -> Result<Ptr, RecorderError> {
+        let config_voice = config.voice;
+        let ptr = self.create_session(config.with_autosave(false).with_chunk_url(None));
+        let session = self.session_mut(ptr)?;
+        session.info = autosave.stream_info();
+        session.codec = codec;
+        session.voice = config_voice.map(|voice| new_voice_gate(voice, session.info));
+        session.autosave = Some(AutosaveWriter::resume(autosave));
+        Ok(ptr)
+    }
+
+    /// # Description
+    /// whether a session already writes to the autosave stored under `id`
+    fn autosave_in_use(&self, id: u64) -> bool {
+        self.processor_list.iter().any(|session| {
+            session
+                .autosave
+                .as_ref()
+                .is_some_and(|autosave| autosave.id() == id)
+        })
+    }
+
+    /// # Description
+    /// whether any session is holding on to the microphone
+    fn capturing(&self) -> bool {
+        self.processor_list
+            .iter()
+            .any(|session| session.capture.is_some())
+    }
+
+    fn playback(&self, session: Ptr) -> Option<PlaybackStatus> {
+        let session = self.processor_list.get(session)?.data()?;
+        session
+            .preview
+            .as_ref()
+            .map(|preview| preview.status(&self.ctx))
+    }
+
+    /// # Description
+    /// the preview of `session`, the recording is decoded the first time around
+    fn preview_mut(
+        &mut self,
+        session: Ptr,
+    ) -> Result<(&AudioContext, &mut Preview), RecorderError> {
+        let session = self
+            .processor_list
+            .get_mut(session)
+            .and_then(|node| node.data_mut())
+            .ok_or_else(unknown_session)?;
+        if session.preview.is_none() {
+            let (info, channels) = decode_planar(&session.snapshot()?[..])
+                .map_err(|err| RecorderError::Codec(err.to_string()))?;
+            session.preview = Some(Preview::new(&self.ctx, info, &channels)?);
+        }
+        let preview = session.preview.as_mut().ok_or_else(unknown_session)?;
+        Ok((&self.ctx, preview))
+    }
+}
+
+impl Drop for RecorderState {
+    fn drop(&mut self) {
+        self.processor_list
+            .iter_mut()
+            .for_each(|session| session.stop());
+        if let Some(stream) = self.stream.take() {
+            stream_tracks(&stream).for_each(|track| track.stop());
+        }
+        if let Some(status) = self.permission_status.take() {
+            status.set_onchange(None);
+        }
+        self.ctx.close().ok();
+    }
+}
+
+fn stream_tracks(stream: &MediaStream) -> impl Iterator<Item = MediaStreamTrack> {
+    stream
+        .get_tracks()
+        .iter()
+        .filter_map(|track| track.dyn_into::<MediaStreamTrack>().ok())
+        .collect::<Vec<_>>()
+        .into_iter()
+}
+
+/// # Description
+/// Owns the microphone, the audio context and every recorder session bound to it
+/// ## Comments
+/// - event handlers only hold weak handles to the state
+/// - once the `Recorder` is dropped (or `destroy`ed) its event handlers stop doing anything
+/// - dropping a `Recorder` stops all captures and releases the microphone
+/// - while a `Recorder` is around, forms waiting in the `OfflineQueue` are sent again whenever the
+///   browser comes back online
+/// - the microphone picked with `select_input` (or a `select.recorder_input` on the page) is
+///   remembered, when it's unplugged recording carries on with the default one
+/// - the browser's processing of the microphone signal is left at its defaults, unless the
+///   recorder is created `with_constraints`
+/// - the microphone is only asked for once something gets recorded, and let go of once nothing has
+///   been recorded for `IDLE_RELEASE_IN_SECS`, see `set_idle_release`
+/// - whether the page may use the microphone is kept in the document element's `data-microphone`
+///   attribute (`granted`, `denied`, `prompt` or `unknown`), see `MicrophonePermission`
+/// - the futures its methods return don't borrow it, so they can be handed to javascript
+pub struct Recorder {
+    state: Rc<RefCell<RecorderState>>,
+    on_click: Function,
+    on_submit: Function,
+    on_seek: Function,
+    on_online: Function,
+    on_input_change: Function,
+    on_device_change: Function,
+}
+
+impl Recorder {
+    /// # Description
+    /// sets up capture, the microphone is asked for once something gets recorded
+    pub async fn new() -> Result<Self, RecorderError> {
+        Self::with_constraints(CaptureConstraints::default()).await
+    }
+
+    /// # Description
+    /// sets up capture, the microphone gets opened with `constraints`
+    pub async fn with_constraints(constraints: CaptureConstraints) -> Result<Self, RecorderError> {
+        let window =
+            web_sys::window().ok_or_else(|| RecorderError::Dom("no window".to_string()))?;
+        let ctx = AudioContext::new().map_err(RecorderError::audio)?;
+
+        // prefer capturing off the main thread, fall back to the script processor otherwise
+        let capture_backend = match register_recorder_worklet(&ctx).await {
+            Ok(_) => CaptureBackend::Worklet,
+            Err(err) => {
+                console::log_1(
+                    &"audio worklet unavailable, falling back to ScriptProcessorNode".into(),
+                );
+                console::log_1(&err);
+                CaptureBackend::ScriptProcessor
+            }
+        };
+
+        let state = Rc::new(RefCell::new(RecorderState {
+            ctx,
+            stream: None,
+            input_device: preferred_input(),
+            constraints,
+            idle_release: Some(IDLE_RELEASE_IN_SECS),
+            idle_generation: 0,
+            permission_status: None,
+            capture_backend,
+            processor_list: LinkedList::new(),
+            progress_listener: None,
+        }));
+
+        let click_state = Rc::downgrade(&state);
+        let on_click = closure_to_function(move |e: MouseEvent| {
+            if let Some(state) = click_state.upgrade() {
+                if let Err(err) = on_click(&state, &e) {
+                    report_event_error(&e, &err);
+                }
+            }
+        });
+
+        let submit_state = Rc::downgrade(&state);
+        let on_submit = closure_to_function(move |e: FocusEvent| {
+            if let Some(state) = submit_state.upgrade() {
+                if let Err(err) = on_submit(&state, &e) {
+                    report_event_error(&e, &err);
+                }
+            }
+        });
+
+        let seek_state = Rc::downgrade(&state);
+        let on_seek = closure_to_function(move |e: Event| {
+            if let Some(state) = seek_state.upgrade() {
+                if let Err(err) = on_seek(&state, &e) {
+                    report_event_error(&e, &err);
+                }
+            }
+        });
+
+        let on_online = offline::retry_when_online()?;
+
+        let input_state = Rc::downgrade(&state);
+        let on_input_change = closure_to_function(move |e: Event| {
+            on_input_change(input_state.clone(), &e);
+        });
+        window
+            .add_event_listener_with_callback("change", &on_input_change)
+            .map_err(RecorderError::dom)?;
+        let device_state = Rc::downgrade(&state);
+        let on_device_change = closure_to_function(move |_: Event| {
+            spawn_local(follow_inputs(device_state.clone()));
+        });
+        // every recorder on the page follows devices, so none of them may take the handler slot
+        media_devices()?
+            .add_event_listener_with_callback("devicechange", &on_device_change)
+            .map_err(RecorderError::dom)?;
+        spawn_local(follow_inputs(Rc::downgrade(&state)));
+        spawn_local(watch_permission(Rc::downgrade(&state)));
+
+        Ok(Self {
+            state,
+            on_click,
+            on_submit,
+            on_seek,
+            on_online,
+            on_input_change,
+            on_device_change,
+        })
+    }
+
+    /// # Description
+    /// makes `button` (and the form it sits in) drive this recorder
+    /// ## Comments
+    /// - attaching the same button twice is harmless
+    /// - a record button that doesn't have a session yet gets back whatever an earlier visit to the
+    ///   page autosaved for it, see `recover_session`
+    pub fn attach(&self, button: &HtmlButtonElement) -> Result<(), RecorderError> {
+        let form = ParentIter::new(button)
+            .find_map(|e| e.dyn_into::<HtmlFormElement>().ok())
+            .ok_or_else(|| RecorderError::Dom("button must be imbedded into a form".to_string()))?;
+        form.set_onsubmit(Some(&self.on_submit));
+        button.set_onclick(Some(&self.on_click));
+        for input in seek_inputs(button) {
+            input.set_oninput(Some(&self.on_seek));
+        }
+
+        let fresh = !button.has_attribute("data-state") && !button.has_attribute("data-action");
+        if fresh && recorder_autosave(button) {
+            let (handle, button) = (Rc::downgrade(&self.state), button.clone());
+            spawn_local(async move {
+                if let Err(err) = recover_session(handle, button).await {
+                    console::error_1(&err.to_string().into());
+                }
+            });
+        }
+        Ok(())
+    }
+
+    /// # Description
+    /// unbinds `button`, its recording (if any) is discarded
+    pub fn detach(&self, button: &HtmlButtonElement) {
+        button.set_onclick(None);
+        for input in seek_inputs(button) {
+            input.set_oninput(None);
+        }
+        if let Some(data) = button.get_attribute("data-state") {
+            // a malformed state has no session to release
+            if let Ok(state) = RecordState::from_string(&data) {
+                self.remove_session(state.processing_node);
+            }
+            button.remove_attribute("data-state").ok();
+            button.remove_attribute("style").ok();
+        }
+    }
+
+    /// # Description
+    /// stops every recording and releases the microphone
+    pub fn destroy(self) {
+        drop(self)
+    }
+
+    /// # Description
+    /// the microphone recordings come from, `None` for the browser's default
+    pub fn input_device(&self) -> Option<String> {
+        self.state.borrow().input_device.clone()
+    }
+
+    /// # Description
+    /// switches every recording over to the microphone `device_id` names, `None` goes back to the
+    /// browser's default
+    /// ## Comments
+    /// - the choice is remembered for later visits
+    /// - recordings that are running carry on with the new microphone, a closed microphone stays
+    ///   closed until something gets recorded
+    pub fn select_input(
+        &self,
+        device_id: Option<&str>,
+    ) -> impl Future<Output = Result<(), RecorderError>> + 'static {
+        select_input(Rc::downgrade(&self.state), device_id.map(String::from))
+    }
+
+    /// # Description
+    /// what the microphone was asked for
+    pub fn constraints(&self) -> CaptureConstraints {
+        self.state.borrow().constraints
+    }
+
+    /// # Description
+    /// reopens the microphone with `constraints`, recordings that are running carry on with it
+    /// ## Comments
+    /// - a closed microphone stays closed, it's opened with `constraints` once it's needed
+    pub fn set_constraints(
+        &self,
+        constraints: CaptureConstraints,
+    ) -> impl Future<Output = Result<(), RecorderError>> + 'static {
+        set_constraints(Rc::downgrade(&self.state), constraints)
+    }
+
+    /// # Description
+    /// what the browser actually applied to the microphone, which may differ from `constraints`
+    /// ## Comments
+    /// - fails while the microphone isn't open
+    pub fn capture_settings(&self) -> Result<CaptureSettings, RecorderError> {
+        let state = self.state.borrow();
+        let track = state
+            .stream
+            .as_ref()
+            .and_then(|stream| stream_tracks(stream).next())
+            .ok_or_else(closed_microphone)?;
+        CaptureSettings::of_track(&track)
+    }
+
+    /// # Description
+    /// whether the microphone is open right now
+    pub fn microphone_open(&self) -> bool {
+        self.state.borrow().stream.is_some()
+    }
+
+    /// # Description
+    /// asks for the microphone, unless it's open already
+    /// ## Comments
+    /// - the user gets prompted the first time around
+    /// - the microphone is let go of again once it sat idle, see `set_idle_release`
+    pub fn open_microphone(&self) -> impl Future<Output = Result<(), RecorderError>> + 'static {
+        open_microphone(Rc::downgrade(&self.state))
+    }
+
+    /// # Description
+    /// lets go of the microphone right away, unless something is being recorded
+    pub fn release_microphone(&self) {
+        let mut state = self.state.borrow_mut();
+        if !state.capturing() {
+            release_stream(&mut state);
+        }
+    }
+
+    /// # Description
+    /// how long the microphone stays open once nothing is being recorded anymore
+    /// ## Parameters
+    /// - `secs` of `None` keeps it open until the recorder is dropped
+    pub fn set_idle_release(&self, secs: Option<f64>) {
+        self.state.borrow_mut().idle_release = secs.filter(|secs| *secs >= 0.0);
+        release_when_idle(&self.state);
+    }
+
+    /// # Description
+    /// creates a session that isn't bound to any button
+    /// ## Returns
+    /// the key every other session method expects
+    pub fn create_session(&self, config: SessionConfig) -> Ptr {
+        self.state.borrow_mut().create_session(config)
+    }
+
+    /// # Description
+    /// discards `session` along with its recording
+    pub fn remove_session(&self, session: Ptr) {
+        let removed = self.state.borrow_mut().processor_list.remove_at(session);
+        if let Some(mut session) = removed {
+            session.stop();
+            session.clear_autosave();
+        }
+        release_when_idle(&self.state);
+    }
+
+    /// # Description
+    /// starts recording, or resumes a paused recording
+    /// ## Comments
+    /// - starting a recording takes an open microphone, see `record_when_open`
+    pub fn record(&self, session: Ptr) -> Result<RecordStatus, RecorderError> {
+        change_status(&self.state, session, RecordStatus::Recording)
+    }
+
+    /// # Description
+    /// opens the microphone when it isn't, then starts (or resumes) recording
+    pub fn record_when_open(
+        &self,
+        session: Ptr,
+    ) -> impl Future<Output = Result<RecordStatus, RecorderError>> + 'static {
+        let handle = Rc::downgrade(&self.state);
+        async move {
+            open_microphone(handle.clone()).await?;
+            let handle = handle.upgrade().ok_or_else(closed_microphone)?;
+            change_status(&handle, session, RecordStatus::Recording)
+        }
+    }
+
+    /// # Description
+    /// pauses a running recording, the capture is kept alive
+    pub fn pause(&self, session: Ptr) -> Result<RecordStatus, RecorderError> {
+        change_status(&self.state, session, RecordStatus::Paused)
+    }
+
+    /// # Description
+    /// stops recording, the next take gets appended to what was recorded so far
+    pub fn stop(&self, session: Ptr) -> Result<RecordStatus, RecorderError> {
+        change_status(&self.state, session, RecordStatus::Stopped)
+    }
+
+    pub fn status(&self, session: Ptr) -> Option<RecordStatus> {
+        self.state
+            .borrow_mut()
+            .session_mut(session)
+            .map(|session| session.status)
+            .ok()
+    }
+
+    /// # Description
+    /// stops `session` and hands out its encoded recording, the session starts over afterwards
+    pub fn take_recording(&self, session: Ptr) -> Result<Vec<u8>, RecorderError> {
+        self.stop(session)?;
+        self.state
+            .borrow_mut()
+            .session_mut(session)?
+            .take_recording()
+    }
+
+    /// # Description
+    /// `session`'s encoded recording so far, see `RecorderSession::snapshot`
+    pub fn snapshot(&self, session: Ptr) -> Result<Vec<u8>, RecorderError> {
+        self.state.borrow_mut().session_mut(session)?.snapshot()
+    }
+
+    /// # Description
+    /// `session`'s recording as it gets uploaded, see `RecorderSession::recording`
+    pub fn recording(&self, session: Ptr) -> Result<Vec<u8>, RecorderError> {
+        self.state.borrow_mut().session_mut(session)?.recording()
+    }
+
+    /// # Description
+    /// `session`'s recording so far as a wav file, see `RecorderSession::export_wav`
+    pub fn export_wav(&self, session: Ptr) -> Result<Vec<u8>, RecorderError> {
+        self.state.borrow_mut().session_mut(session)?.export_wav()
+    }
+
+    /// # Description
+    /// stops `session` and hands out its chunked upload, see `RecorderSession::take_upload`
+    pub fn take_upload(&self, session: Ptr) -> Result<Option<ChunkUploader>, RecorderError> {
+        self.stop(session)?;
+        Ok(self.state.borrow_mut().session_mut(session)?.take_upload())
+    }
+
+    /// # Description
+    /// stops recording and plays back what has been recorded so far
+    pub fn play(&self, session: Ptr) -> Result<(), RecorderError> {
+        start_preview(&self.state, session)
+    }
+
+    /// # Description
+    /// stops playing back `session`, the position is kept for the next `play`
+    pub fn stop_playback(&self, session: Ptr) {
+        stop_preview(&self.state, session);
+    }
+
+    /// # Description
+    /// moves playback of a stopped recording to `position` (in seconds)
+    pub fn seek(&self, session: Ptr, position: f64) -> Result<(), RecorderError> {
+        seek_preview(&self.state, session, position)
+    }
+
+    /// # Description
+    /// where playback of `session` is at, `None` until it has been played or scrubbed
+    pub fn playback(&self, session: Ptr) -> Option<PlaybackStatus> {
+        self.state.borrow().playback(session)
+    }
+
+    /// # Description
+    /// stops `session` and throws its recording away, see `RecorderSession::discard`
+    pub fn discard(&self, session: Ptr) -> Result<RecordStatus, RecorderError> {
+        let status = self.stop(session)?;
+        self.state.borrow_mut().session_mut(session)?.discard();
+        Ok(status)
+    }
+
+    pub fn set_level_listener(&self, session: Ptr, listener: Option<LevelListener>) {
+        if let Ok(session) = self.state.borrow_mut().session_mut(session) {
+            session.level_listener = listener;
+        }
+    }
+
+    pub fn set_status_listener(&self, session: Ptr, listener: Option<StatusListener>) {
+        if let Ok(session) = self.state.borrow_mut().session_mut(session) {
+            session.status_listener = listener;
+        }
+    }
+
+    pub fn set_voice_listener(&self, session: Ptr, listener: Option<VoiceListener>) {
+        if let Ok(session) = self.state.borrow_mut().session_mut(session) {
+            session.voice_listener = listener;
+        }
+    }
+
+    /// # Description
+    /// gets told how far along every form submitted through this recorder is
+    pub fn set_progress_listener(&self, listener: Option<ProgressListener>) {
+        self.state.borrow_mut().progress_listener = listener;
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        if let Some(window) = web_sys::window() {
+            window
+                .remove_event_listener_with_callback("online", &self.on_online)
+                .ok();
+            window
+                .remove_event_listener_with_callback("change", &self.on_input_change)
+                .ok();
+        }
+        if let Ok(media_devices) = media_devices() {
+            media_devices
+                .remove_event_listener_with_callback("devicechange", &self.on_device_change)
+                .ok();
+        }
+    }
+}
+
+fn unknown_session() -> RecorderError {
+    RecorderError::MalformedState("unknown recorder session".to_string())
+}
+
+fn closed_microphone() -> RecorderError {
+    RecorderError::Audio("the microphone isn't open".to_string())
+}
+
+/// # Description
+/// reads a positive integer attribute off the recorder a button belongs to
+pub fn recorder_attribute(button: &HtmlButtonElement, name: &str) -> Option<u32> {
+    button
+        .closest(".recorder_button")
+        .ok()
+        .flatten()
+        .and_then(|container| container.get_attribute(name))
+        .and_then(|value| value.trim().parse::<u32>().ok())
+        .filter(|&value| value > 0)
+}
+
+/// # Description
+/// reads the optional `data-sample-rate` attribute off the recorder a button belongs to
+/// ## Comments
+/// - recordings are resampled to this rate, when it's missing the `AudioContext` rate is used
+pub fn recorder_sample_rate(button: &HtmlButtonElement) -> Option<u32> {
+    recorder_attribute(button, "data-sample-rate")
+}
+
+/// # Description
+/// reads the optional `data-channels` attribute off the recorder a button belongs to
+/// ## Comments
+/// - defaults to mono
+pub fn recorder_channels(button: &HtmlButtonElement) -> u32 {
+    recorder_attribute(button, "data-channels").unwrap_or(1)
+}
+
+/// # Description
+/// reads the optional `data-field-name` attribute off the recorder a button belongs to
+/// ## Comments
+/// - defaults to `audio_recording`
+/// - recordings are uploaded as `<field-name>.adhoc`, the default field keeps `rec.adhoc`
+pub fn recorder_field_name(button: &HtmlButtonElement) -> (String, String) {
+    button
+        .closest(".recorder_button")
+        .ok()
+        .flatten()
+        .and_then(|container| container.get_attribute("data-field-name"))
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty() && name != DEFAULT_FIELD_NAME)
+        .map(|name| {
+            let file_name = format!("{}.adhoc", name);
+            (name, file_name)
+        })
+        .unwrap_or_else(|| (DEFAULT_FIELD_NAME.to_string(), "rec.adhoc".to_string()))
+}
+
+/// # Description
+/// reads the optional `data-autosave` attribute off the recorder a button belongs to
+/// ## Comments
+/// - autosaving is opt in, like `SessionConfig::default`, microphone audio only ends up in
+///   browser storage on pages that ask for it with `data-autosave` (or `data-autosave="on"`)
+pub fn recorder_autosave(button: &HtmlButtonElement) -> bool {
+    button
+        .closest(".recorder_button")
+        .ok()
+        .flatten()
+        .and_then(|container| container.get_attribute("data-autosave"))
+        .is_some_and(|autosave| autosave.trim() != "off")
+}
+
+/// # Description
+/// reads the optional `data-voice-activated` and `data-silence-stop` attributes off the recorder a
+/// button belongs to
+/// ## Comments
+/// - a voice activated recorder only records while someone speaks, the silence before and after is
+///   left out
+/// - it stops on its own after `data-silence-stop` seconds of silence (2 by default), `off`
+///   keeps it recording until it's stopped
+pub fn recorder_voice_activation(button: &HtmlButtonElement) -> Option<VoiceActivation> {
+    let container = button.closest(".recorder_button").ok().flatten()?;
+    if !container.has_attribute("data-voice-activated") {
+        return None;
+    }
+    let voice = VoiceActivation::default();
+    Some(match container.get_attribute("data-silence-stop") {
+        Some(secs) if secs.trim() == "off" => voice.with_stop_after_silence(None),
+        Some(secs) => match secs.trim().parse::<f32>() {
+            Ok(secs) if secs > 0.0 => voice.with_stop_after_silence(Some(secs)),
+            _ => voice,
+        },
+        None => voice,
+    })
+}
+
+/// # Description
+/// reads the optional `data-trim-silence` attribute off the recorder a button belongs to
+/// ## Comments
+/// - the silence before the first word and after the last one is cut out of the recording before
+///   it's uploaded, and pauses are shortened to the attribute's value in seconds (1 by default),
+///   `off` keeps them
+pub fn recorder_trim_silence(button: &HtmlButtonElement) -> Option<TrimConfig> {
+    let container = button.closest(".recorder_button").ok().flatten()?;
+    let trim = TrimConfig::default();
+    Some(match container.get_attribute("data-trim-silence")?.trim() {
+        "off" => trim.with_max_gap(None),
+        secs => match secs.parse::<f32>() {
+            Ok(secs) if secs >= 0.0 => trim.with_max_gap(Some(secs)),
+            _ => trim,
+        },
+    })
+}
+
+/// # Description
+/// reads the optional `data-chunk-url` attribute off the recorder a button belongs to
+/// ## Comments
+/// - when it's set the recording is uploaded in segments while it's being recorded
+pub fn recorder_chunk_url(button: &HtmlButtonElement) -> Option<String> {
+    button
+        .closest(".recorder_button")
+        .ok()
+        .flatten()
+        .and_then(|container| container.get_attribute("data-chunk-url"))
+        .map(|url| url.trim().to_string())
+        .filter(|url| !url.is_empty())
+}
+
+pub fn generate_beep_noise(freq: f32, sample_rate: f32) -> Vec<f32> {
+    let mut t = 0.0f32;
+    let dt = 1.0 / sample_rate;
+    let mut beep_buffer = Vec::new();
+
+    while t < BEEP_DURATION_IN_SECS {
+        let s = math::linear_step(
+            t,
+            BEEP_DURATION_IN_SECS - BEEP_DAMPEN_DELTA,
+            BEEP_DURATION_IN_SECS,
+        );
+        let u = math::linear_step(t, 0.0, RAMP_UP_DURATION_IN_SECS);
+        let ramp_up = u * u;
+        let dampening = 1.0 - s * s;
+        let sample = (t * freq).sin() * 0.5 * dampening * ramp_up;
+        beep_buffer.push(sample);
+        t += dt;
+    }
+
+    beep_buffer
+}
+
+pub fn play_beep_noise(ctx: &AudioContext, freq: f32) -> Result<(), RecorderError> {
+    let sample_rate = ctx.sample_rate();
+    let beep_pcm = generate_beep_noise(freq, sample_rate);
+    let source = AudioBufferSourceNode::new(ctx).map_err(RecorderError::audio)?;
+    let buffer = ctx
+        .create_buffer(1, beep_pcm.len() as u32, sample_rate)
+        .map_err(RecorderError::audio)?;
+    buffer
+        .copy_to_channel(&beep_pcm, 0)
+        .map_err(RecorderError::audio)?;
+    source.set_buffer(Some(&buffer));
+    source
+        .connect_with_audio_node(&ctx.destination())
+        .map_err(RecorderError::audio)?;
+    source.start().map_err(RecorderError::audio)
+}
+
+/// # Description
+/// tells the page something went wrong with the recorder `target` belongs to
+/// ## Comments
+/// - a bubbling `recordererror` event is dispatched on `target`, its `detail` is the error
+/// - the message is shown in a `.recorder_error` element inside the recorder container
+/// - when `target` isn't inside a recorder (a form for instance) every recorder it contains shows the message
+pub fn report_error(target: &Element, err: &RecorderError) {
+    console::error_1(&err.to_string().into());
+
+    let init = CustomEventInit::new();
+    init.set_bubbles(true);
+    init.set_detail(&JsValue::from(err.clone()));
+    if let Ok(event) = CustomEvent::new_with_event_init_dict(ERROR_EVENT, &init) {
+        target.dispatch_event(&event).ok();
+    }
+
+    let message = err.to_string();
+    match target.closest(".recorder_button").ok().flatten() {
+        Some(container) => show_error_message(&container, Some(&message)),
+        None => DomIter::by_class_name(target, "recorder_button")
+            .collect::<Vec<_>>()
+            .iter()
+            .for_each(|container| show_error_message(container, Some(&message))),
+    }
+}
+
+fn report_event_error(event: &Event, err: &RecorderError) {
+    match event
+        .current_target()
+        .and_then(|target| target.dyn_into::<Element>().ok())
+    {
+        Some(target) => report_error(&target, err),
+        None => console::error_1(&err.to_string().into()),
+    }
+}
+
+/// # Description
+/// shows `message` in the recorder container, `None` clears whatever was shown before
+fn show_error_message(container: &Element, message: Option<&str>) {
+    let existing = container.query_selector(".recorder_error").ok().flatten();
+    match (existing, message) {
+        (Some(element), Some(message)) => element.set_text_content(Some(message)),
+        (Some(element), None) => element.remove(),
+        (None, Some(message)) => {
+            let element = container
+                .owner_document()
+                .and_then(|document| document.create_element("span").ok());
+            if let Some(element) = element {
+                element.set_class_name("recorder_error");
+                element.set_attribute("role", "alert").ok();
+                element.set_text_content(Some(message));
+                container.append_child(&element).ok();
+            }
+        }
+        (None, None) => (),
+    }
+}
+
+/// # Description
+/// starts capturing microphone audio into `session`
+/// ## Parameters
+/// - `announce` plays the start beep, unless the session has beeps turned off
+fn start_capture(
+    handle: Weak<RefCell<RecorderState>>,
+    state: &mut RecorderState,
+    session: Ptr,
+    announce: bool,
+) -> Result<(), RecorderError> {
+    let ctx = &state.ctx;
+    let (info, beep) = state
+        .processor_list
+        .get(session)
+        .and_then(|node| node.data())
+        .map(|session| (session.info, session.beep && announce))
+        .ok_or_else(unknown_session)?;
+
+    let stream = state.stream.as_ref().ok_or_else(closed_microphone)?;
+    let source = ctx
+        .create_media_stream_source(stream)
+        .map_err(RecorderError::audio)?;
+    let config = CaptureConfig::new(state.capture_backend)
+        .with_sample_rate(Some(info.frequency() as u32))
+        .with_channels(info.channels() as u32);
+
+    // skip the beep (and whatever echo it leaves behind)
+    let warmup_samples = if beep {
+        play_beep_noise(ctx, 5000.0)?;
+        ((BEEP_DURATION_IN_SECS + 0.5) * info.frequency() as f32) as usize * info.channels()
+    } else {
+        0
+    };
+    let capture =
+        WebCaptureSource::new(ctx, &source, config, move |microphone: &mut BlockBuffer| {
+            let state = match handle.upgrade() {
+                Some(state) => state,
+                None => return,
+            };
+            let (amplitude, voice_event, level_listener, voice_listener) = {
+                let mut state = state.borrow_mut();
+                let session = match state.session_mut(session) {
+                    Ok(session) => session,
+                    Err(_) => return,
+                };
+                let (amplitude, voice_event) = session.record_captured(microphone);
+                (
+                    amplitude,
+                    voice_event,
+                    session.level_listener.clone(),
+                    session.voice_listener.clone(),
+                )
+            };
+
+            // listeners run outside of the borrow so they are free to call back into the recorder
+            if let (Some(amplitude), Some(listener)) = (amplitude, level_listener) {
+                listener(amplitude);
+            }
+            match (voice_event, voice_listener) {
+                (Some(VoiceEvent::SpeechStarted), Some(listener)) => listener(true),
+                (Some(VoiceEvent::SpeechEnded), Some(listener)) => listener(false),
+                // the capture calling back can't be torn down from inside its own callback
+                (Some(VoiceEvent::SilenceTimeout), _) => {
+                    let handle = handle.clone();
+                    spawn_local(async move {
+                        let stopped = handle
+                            .upgrade()
+                            .map(|handle| change_status(&handle, session, RecordStatus::Stopped));
+                        if let Some(Err(err)) = stopped {
+                            console::error_1(&err.to_string().into());
+                        }
+                    });
+                }
+                _ => {}
+            }
+        })
+        .map_err(RecorderError::audio)?;
+
+    let session = state.session_mut(session)?;
+    session.capture = Some(capture);
+    session.gate = RecordGate::new(info.channels());
+    session.warmup_samples = warmup_samples;
+    Ok(())
+}
+
+/// # Description
+/// moves `session` over to `target`, starting or tearing down its capture as needed
+/// ## Returns
+/// the status the session ended up in, a stopped session can't be paused
+fn change_status(
+    handle: &Rc<RefCell<RecorderState>>,
+    session: Ptr,
+    target: RecordStatus,
+) -> Result<RecordStatus, RecorderError> {
+    let status_listener = {
+        let mut state = handle.borrow_mut();
+        let current = state.session_mut(session)?.status;
+        match (current, target) {
+            _ if current == target => return Ok(current),
+            (RecordStatus::Stopped, RecordStatus::Paused) => return Ok(current),
+            (_, RecordStatus::Stopped) => {
+                console::log_1(&"stop recording..".into());
+                let session = state.session_mut(session)?;
+                session.stop();
+                if session.beep {
+                    play_beep_noise(&state.ctx, 1000.0)?;
+                }
+            }
+            // takes recorded after a stop are appended to the same stream
+            (RecordStatus::Stopped, _) => {
+                // a context created before the page was interacted with starts out suspended
+                state.ctx.resume().ok();
+                // the preview no longer matches once more gets recorded
+                state.session_mut(session)?.preview = None;
+                start_capture(Rc::downgrade(handle), &mut state, session, true)?;
+            }
+            // pausing keeps the capture node alive so resuming appends to the same stream
+            (_, RecordStatus::Paused) => {
+                console::log_1(&"pause recording..".into());
+                if let Some(autosave) = state.session_mut(session)?.autosave.as_mut() {
+                    autosave.flush();
+                }
+            }
+            (_, RecordStatus::Recording) => console::log_1(&"resume recording..".into()),
+        }
+        let session = state.session_mut(session)?;
+        session.status = target;
+        session.status_listener.clone()
+    };
+
+    if let Some(listener) = status_listener {
+        listener(target);
+    }
+    if target == RecordStatus::Stopped {
+        release_when_idle(handle);
+    }
+    Ok(target)
+}
+
+/// # Description
+/// records from `stream` from now on, running captures are moved over to it
+fn switch_stream(
+    handle: &Rc<RefCell<RecorderState>>,
+    stream: MediaStream,
+    input_device: Option<String>,
+) -> Result<(), RecorderError> {
+    let mut state = handle.borrow_mut();
+    if let Some(previous) = state.stream.replace(stream.clone()) {
+        stream_tracks(&previous).for_each(|track| track.stop());
+    }
+    state.input_device = input_device;
+
+    // a capture is tied to a source node of the stream it started on
+    let capturing = LinkedListIterator::new(&state.processor_list)
+        .filter(|&session| {
+            state.processor_list[session]
+                .data()
+                .is_some_and(|session| session.capture.is_some())
+        })
+        .collect::<Vec<_>>();
+    for session in capturing {
+        state.session_mut(session)?.end_capture();
+        start_capture(Rc::downgrade(handle), &mut state, session, false)?;
+    }
+    report_unmet_constraints(&stream, &state.constraints);
+    drop(state);
+
+    watch_tracks(Rc::downgrade(handle), &stream);
+    Ok(())
+}
+
+/// # Description
+/// opens the microphone of `handle`, unless it's open already
+/// ## Comments
+/// - the picked microphone may be gone by now, the default one is opened then
+/// - the outcome is mirrored onto the page, see `show_permission`
+async fn open_microphone(handle: Weak<RefCell<RecorderState>>) -> Result<(), RecorderError> {
+    let (input_device, constraints) = match handle.upgrade() {
+        Some(handle) => {
+            let state = handle.borrow();
+            if state.stream.is_some() {
+                return Ok(());
+            }
+            (state.input_device.clone(), state.constraints)
+        }
+        None => return Ok(()),
+    };
+    let opened = match open_input(input_device.as_deref(), &constraints).await {
+        Err(RecorderError::NoInputDevice) if input_device.is_some() => {
+            open_input(None, &constraints)
+                .await
+                .map(|stream| (stream, None))
+        }
+        opened => opened.map(|stream| (stream, input_device)),
+    };
+    match &opened {
+        Ok(_) => show_permission(MicrophonePermission::Granted),
+        Err(RecorderError::PermissionDenied) => show_permission(MicrophonePermission::Denied),
+        Err(_) => {}
+    }
+    let (stream, input_device) = opened?;
+    match handle.upgrade() {
+        // another caller may have opened it while the user was being asked
+        Some(handle) if handle.borrow().stream.is_some() => {
+            stream_tracks(&stream).for_each(|track| track.stop())
+        }
+        Some(handle) => {
+            switch_stream(&handle, stream, input_device)?;
+            release_when_idle(&handle);
+        }
+        None => stream_tracks(&stream).for_each(|track| track.stop()),
+    }
+    // labels only show up once the microphone was allowed
+    spawn_local(follow_inputs(handle));
+    Ok(())
+}
+
+/// # Description
+/// lets go of the microphone once it sat idle for the recorder's `idle_release`
+/// ## Comments
+/// - the release is called off when something gets recorded in the meantime
+fn release_when_idle(handle: &Rc<RefCell<RecorderState>>) {
+    let (delay, generation) = {
+        let mut state = handle.borrow_mut();
+        let delay = match state.idle_release {
+            Some(delay) if state.stream.is_some() && !state.capturing() => delay,
+            _ => return,
+        };
+        state.idle_generation = state.idle_generation.wrapping_add(1);
+        (delay, state.idle_generation)
+    };
+    let handle = Rc::downgrade(handle);
+    spawn_local(async move {
+        web_utils::sleep((delay * 1000.0) as i32).await;
+        if let Some(handle) = handle.upgrade() {
+            let mut state = handle.borrow_mut();
+            if state.idle_generation == generation && !state.capturing() {
+                release_stream(&mut state);
+            }
+        }
+    });
+}
+
+fn release_stream(state: &mut RecorderState) {
+    if let Some(stream) = state.stream.take() {
+        console::log_1(&"releasing the microphone..".into());
+        stream_tracks(&stream).for_each(|track| track.stop());
+    }
+}
+
+/// # Description
+/// mirrors whether the page may use the microphone onto the document element, following the user
+/// changing their mind
+async fn watch_permission(handle: Weak<RefCell<RecorderState>>) {
+    let status = match microphone_permission_status().await {
+        Some(status) => status,
+        None => return show_permission(MicrophonePermission::Unknown),
+    };
+    show_permission(status.state().into());
+    let watched = status.clone();
+    status.set_onchange(Some(&closure_to_function(move |_: Event| {
+        show_permission(watched.state().into())
+    })));
+    match handle.upgrade() {
+        Some(handle) => handle.borrow_mut().permission_status = Some(status),
+        None => status.set_onchange(None),
+    }
+}
+
+/// # Description
+/// sets the document element's `data-microphone` attribute, so the page can tell visitors their
+/// microphone is blocked
+fn show_permission(permission: MicrophonePermission) {
+    if let Some(root) = web_sys::window()
+        .and_then(|window| window.document())
+        .and_then(|document| document.document_element())
+    {
+        root.set_attribute(PERMISSION_ATTRIBUTE, permission.name())
+            .ok();
+    }
+}
+
+/// # Description
+/// warns on the console about processing the browser kept on (or off) against the constraints
+fn report_unmet_constraints(stream: &MediaStream, constraints: &CaptureConstraints) {
+    let settings = match stream_tracks(stream).next() {
+        Some(track) => CaptureSettings::of_track(&track),
+        None => return,
+    };
+    match settings.map(|settings| constraints.unmet(&settings)) {
+        Ok(unmet) if unmet.is_empty() => {}
+        Ok(unmet) => console::warn_1(
+            &format!(
+                "the browser ignored capture constraints: {}",
+                unmet.join(", ")
+            )
+            .into(),
+        ),
+        Err(err) => console::error_1(&err.to_string().into()),
+    }
+}
+
+/// # Description
+/// follows the microphone behind `stream` going away, like a headset being unplugged
+fn watch_tracks(handle: Weak<RefCell<RecorderState>>, stream: &MediaStream) {
+    let on_ended = closure_to_function(move |_: Event| {
+        spawn_local(follow_inputs(handle.clone()));
+    });
+    stream_tracks(stream).for_each(|track| track.set_onended(Some(&on_ended)));
+}
+
+/// # Description
+/// opens the microphone `device_id` names and records from it
+async fn select_input(
+    handle: Weak<RefCell<RecorderState>>,
+    device_id: Option<String>,
+) -> Result<(), RecorderError> {
+    let device_id = device_id.filter(|id| !id.is_empty());
+    set_preferred_input(device_id.as_deref());
+    reopen_microphone(&handle, device_id).await?;
+    show_inputs(&input_devices().await?, handle);
+    Ok(())
+}
+
+/// # Description
+/// records from the microphone `device_id` names from now on
+/// ## Comments
+/// - a microphone that isn't open stays closed, it's opened on `device_id` once it's needed
+async fn reopen_microphone(
+    handle: &Weak<RefCell<RecorderState>>,
+    device_id: Option<String>,
+) -> Result<(), RecorderError> {
+    let constraints = match handle.upgrade() {
+        Some(handle) => {
+            let mut state = handle.borrow_mut();
+            if state.stream.is_none() {
+                state.input_device = device_id;
+                return Ok(());
+            }
+            state.constraints
+        }
+        None => return Ok(()),
+    };
+    let stream = open_input(device_id.as_deref(), &constraints).await?;
+    match handle.upgrade() {
+        Some(handle) => switch_stream(&handle, stream, device_id),
+        // nobody is left to record, let go of the microphone right away
+        None => {
+            stream_tracks(&stream).for_each(|track| track.stop());
+            Ok(())
+        }
+    }
+}
+
+/// # Description
+/// reopens the microphone in use with `constraints` and records from it
+async fn set_constraints(
+    handle: Weak<RefCell<RecorderState>>,
+    constraints: CaptureConstraints,
+) -> Result<(), RecorderError> {
+    let input_device = match handle.upgrade() {
+        Some(handle) => {
+            let mut state = handle.borrow_mut();
+            state.constraints = constraints;
+            state.input_device.clone()
+        }
+        None => return Ok(()),
+    };
+    reopen_microphone(&handle, input_device).await
+}
+
+/// # Description
+/// moves over to the microphone that should be recorded from, after a device got plugged in or
+/// out
+/// ## Comments
+/// - the picked microphone is used when it's plugged in, the default one otherwise
+/// - the stream is reopened as well when its microphone went away
+async fn follow_inputs(handle: Weak<RefCell<RecorderState>>) {
+    if let Err(err) = try_follow_inputs(handle).await {
+        console::error_1(&err.to_string().into());
+    }
+}
+
+async fn try_follow_inputs(handle: Weak<RefCell<RecorderState>>) -> Result<(), RecorderError> {
+    let devices = input_devices().await?;
+    let wanted = pick_input(preferred_input().as_deref(), &devices);
+    let stale = match handle.upgrade() {
+        Some(handle) => {
+            let state = handle.borrow();
+            let ended = state.stream.as_ref().is_some_and(|stream| {
+                stream_tracks(stream)
+                    .all(|track| track.ready_state() == MediaStreamTrackState::Ended)
+            });
+            ended || state.input_device != wanted
+        }
+        None => return Ok(()),
+    };
+    if stale {
+        reopen_microphone(&handle, wanted).await?;
+    }
+    show_inputs(&devices, handle);
+    Ok(())
+}
+
+/// # Description
+/// lists `devices` in every `select.recorder_input` on the page, the microphone in use is selected
+/// ## Comments
+/// - the first option stands for the browser's default microphone and has an empty value
+fn show_inputs(devices: &[InputDevice], handle: Weak<RefCell<RecorderState>>) {
+    let root = match web_sys::window()
+        .and_then(|window| window.document())
+        .and_then(|document| document.document_element())
+    {
+        Some(root) => root,
+        None => return,
+    };
+    let current = handle
+        .upgrade()
+        .and_then(|handle| handle.borrow().input_device.clone())
+        .unwrap_or_default();
+    let selects = DomIter::by_class_name(&root, "recorder_input")
+        .filter_map(|e| e.dyn_into::<HtmlSelectElement>().ok());
+    for select in selects {
+        select.set_length(0);
+        let options = [("default microphone".to_string(), String::new())]
+            .into_iter()
+            .chain(devices.iter().enumerate().map(|(k, device)| {
+                let label = match device.label.as_str() {
+                    "" => format!("microphone {}", k + 1),
+                    label => label.to_string(),
+                };
+                (label, device.id.clone())
+            }))
+            .filter(|(_, id)| id != "default");
+        for (label, id) in options {
+            if let Ok(option) = HtmlOptionElement::new_with_text_and_value(&label, &id) {
+                select.add_with_html_option_element(&option).ok();
+            }
+        }
+        select.set_value(&current);
+    }
+}
+
+/// # Description
+/// switches microphones once a `select.recorder_input` changes
+fn on_input_change(handle: Weak<RefCell<RecorderState>>, event: &Event) {
+    let select = match event
+        .target()
+        .and_then(|target| target.dyn_into::<HtmlSelectElement>().ok())
+        .filter(|select| select.class_list().contains("recorder_input"))
+    {
+        Some(select) => select,
+        None => return,
+    };
+    spawn_local(async move {
+        if let Err(err) = select_input(handle, Some(select.value())).await {
+            report_error(&select, &err);
+        }
+    });
+}
+
+/// # Description
+/// has `session` report back to the record `button` it belongs to
+/// ## Comments
+/// - the button is colored and scaled with the level of the microphone
+/// - the button follows the session stopping on its own, after a voice activated recording fell
+///   silent
+/// - while a voice activated session records, the button's `data-voice` attribute tells whether
+///   someone is `speaking` or it's `silent`
+fn bind_button(session: &mut RecorderSession, button: &HtmlButtonElement) {
+    session.level_listener = Some(button_level_meter(button.clone()));
+    let status_button = button.clone();
+    session.status_listener = Some(Rc::new(move |status: RecordStatus| {
+        let record_state = status_button
+            .get_attribute("data-state")
+            .and_then(|data| RecordState::from_string(&data).ok());
+        if let Some(record_state) = record_state {
+            let record_state = RecordState {
+                status,
+                ..record_state
+            };
+            status_button
+                .set_attribute("data-state", &record_state.to_string())
+                .ok();
+        }
+        if status != RecordStatus::Recording {
+            status_button.remove_attribute("style").ok();
+            status_button.remove_attribute("data-voice").ok();
+        }
+    }));
+    let voice_button = button.clone();
+    session.voice_listener = Some(Rc::new(move |speaking: bool| {
+        let voice = if speaking { "speaking" } else { "silent" };
+        voice_button.set_attribute("data-voice", voice).ok();
+    }));
+}
+
+fn button_level_meter(button: HtmlButtonElement) -> LevelListener {
+    Rc::new(move |amplitude: f32| {
+        let amplitude = amplitude * 10.0;
+        let t = (amplitude.clamp(0.8, 2.0) - 0.8) / (2.0 - 0.8);
+
+        let color_0 = [128.0, 128.0, 128.0];
+        let color_1 = [255.0, 0.0, 0.0];
+        let lerp = math::lerp(color_0, color_1, t * 0.8 + 0.2);
+
+        button
+            .set_attribute(
+                "style",
+                format!(
+                    r"color:rgb({:.2},{:.2},{:.2});
+                    --ggs:{:.3};
+                    ",
+                    lerp[0],
+                    lerp[1],
+                    lerp[2],
+                    (t * t) * 0.8 + 0.8,
+                )
+                .as_str(),
+            )
+            .ok();
+    })
+}
+
+fn on_click(
+    handle: &Rc<RefCell<RecorderState>>,
+    mouse_event: &MouseEvent,
+) -> Result<(), RecorderError> {
+    let button = mouse_event
+        .current_target()
+        .and_then(|t| t.dyn_into::<HtmlButtonElement>().ok())
+        .ok_or_else(|| RecorderError::Dom("recorder handler bound to a non-button".to_string()))?;
+    let container = button.closest(".recorder_button").ok().flatten();
+
+    // the other buttons of a recorder (stop, play, ...) drive the record button sitting next to them
+    let action = button.get_attribute("data-action");
+    let button = match action {
+        Some(_) => match record_button(container.as_ref()) {
+            Some(record_button) => record_button,
+            None => return Ok(()),
+        },
+        None => button,
+    };
+    if is_uploading(&button) {
+        // the recording is being sent, recording into it now would be lost along with the session
+        return Ok(());
+    }
+
+    let record_state = match button.get_attribute("data-state") {
+        Some(data) => RecordState::from_string(&data)?,
+        None if action.is_some() => return Ok(()),
+        None => {
+            // first take on this button, give it a session of its own
+            let mut state = handle.borrow_mut();
+            let session = state.create_session(SessionConfig::for_button(&button));
+            let new_session = state.session_mut(session)?;
+            bind_button(new_session, &button);
+            RecordState {
+                status: RecordStatus::Stopped,
+                processing_node: session,
+                autosave: new_session.autosave.as_ref().map(|autosave| autosave.id()),
+            }
+        }
+    };
+    let session = record_state.processing_node;
+
+    let target = match (action.as_deref(), record_state.status) {
+        (Some("download"), _) => return download_recording(handle, session),
+        // playing back stops the recording, so does throwing it away
+        (Some("play"), _) => {
+            toggle_preview(handle, session, container.as_ref())?;
+            RecordStatus::Stopped
+        }
+        (Some("rerecord"), _) => {
+            change_status(handle, session, RecordStatus::Stopped)?;
+            handle.borrow_mut().session_mut(session)?.discard();
+            if let Some(container) = container.as_ref() {
+                show_playback(container, None);
+            }
+            RecordStatus::Stopped
+        }
+        (Some("stop"), RecordStatus::Stopped) => return Ok(()),
+        (Some("stop"), _) => RecordStatus::Stopped,
+        (Some(_), _) => return Ok(()),
+        (None, RecordStatus::Recording) => RecordStatus::Paused,
+        (None, _) => RecordStatus::Recording,
+    };
+    if target == RecordStatus::Recording && handle.borrow().stream.is_none() {
+        // the microphone is only asked for now, the click is replayed once it's open
+        let opening = button.has_attribute("data-opening");
+        button
+            .set_attribute("data-state", &record_state.to_string())
+            .map_err(RecorderError::dom)?;
+        if !opening {
+            handle.borrow().ctx.resume().ok();
+            button.set_attribute("data-opening", "").ok();
+            spawn_local(record_once_open(Rc::downgrade(handle), button));
+        }
+        return Ok(());
+    }
+    let status = change_status(handle, session, target)?;
+    if status != RecordStatus::Recording {
+        button.remove_attribute("style").ok();
+    }
+    if let Some(container) = container {
+        show_error_message(&container, None);
+        if status == RecordStatus::Recording {
+            show_playback(&container, None);
+        }
+    }
+
+    let record_state = RecordState {
+        status,
+        ..record_state
+    };
+    button
+        .set_attribute("data-state", &record_state.to_string())
+        .map_err(RecorderError::dom)
+}
+
+/// # Description
+/// clicks the record `button` again once the microphone is open
+async fn record_once_open(handle: Weak<RefCell<RecorderState>>, button: HtmlButtonElement) {
+    let opened = open_microphone(handle).await;
+    button.remove_attribute("data-opening").ok();
+    match opened {
+        Ok(_) => button.click(),
+        Err(err) => report_error(&button, &err),
+    }
+}
+
+/// # Description
+/// brings back the recording an earlier visit to the page autosaved for the record `button`
+/// ## Comments
+/// - the most recent autosave made on this page by a recorder with the same field name is used
+/// - the recorder ends up stopped with the recording, ready to be previewed, recorded into or
+///   submitted, and a bubbling `recorderrecovered` event carrying the autosave `id` is dispatched
+///   on the button
+/// - nothing happens should the button get a session of its own in the meantime
+/// - autosaves another tab still writes to are left alone, see `AutosaveInfo::is_live`
+async fn recover_session(
+    handle: Weak<RefCell<RecorderState>>,
+    button: HtmlButtonElement,
+) -> Result<(), RecorderError> {
+    let config = SessionConfig::for_button(&button);
+    let autosaves = Autosaves::open().await?;
+    let page = current_page();
+    // a reload leaves its autosave looking alive for a little while, so the most recent live
+    // autosave is waited for once before settling for an older one
+    let mut waited = false;
+    'candidates: loop {
+        let candidates =
+            autosaves.list().await?.into_iter().filter(|autosave| {
+                autosave.page == page && autosave.field_name == config.field_name
+            });
+        for candidate in candidates {
+            // another recorder on the page may have claimed it already
+            let claimed = match handle.upgrade() {
+                Some(handle) => handle.borrow().autosave_in_use(candidate.id),
+                None => return Ok(()),
+            };
+            if claimed {
+                continue;
+            }
+            // another tab still records into it
+            let now = js_sys::Date::now();
+            if candidate.is_live(now) {
+                if !waited {
+                    waited = true;
+                    web_utils::sleep(candidate.millis_until_abandoned(now).ceil() as i32).await;
+                    continue 'candidates;
+                }
+                continue;
+            }
+            let (autosave, codec) = match autosaves.recover(candidate.id).await? {
+                // a tab that recovered it in the meantime has claimed it
+                Some((autosave, _)) if autosave.is_live(js_sys::Date::now()) => continue,
+                Some(recovered) => recovered,
+                None => continue,
+            };
+
+            let handle = match handle.upgrade() {
+                Some(handle) => handle,
+                None => return Ok(()),
+            };
+            if button.has_attribute("data-state") || handle.borrow().autosave_in_use(autosave.id) {
+                return Ok(());
+            }
+            let id = autosave.id;
+            let session = handle
+                .borrow_mut()
+                .restore_session(config, autosave, codec)?;
+            bind_button(handle.borrow_mut().session_mut(session)?, &button);
+            let record_state = RecordState {
+                status: RecordStatus::Stopped,
+                processing_node: session,
+                autosave: Some(id),
+            };
+            button
+                .set_attribute("data-state", &record_state.to_string())
+                .map_err(RecorderError::dom)?;
+
+            let detail = Object::new();
+            Reflect::set(&detail, &"id".into(), &JsValue::from(id as f64)).ok();
+            dispatch_detail(&button, RECOVERED_EVENT, &detail);
+            return Ok(());
+        }
+        return Ok(());
+    }
+}
+
+/// # Description
+/// the scrub inputs of the recorder `button` belongs to
+fn seek_inputs(button: &HtmlButtonElement) -> Vec<HtmlInputElement> {
+    match button.closest(".recorder_button").ok().flatten() {
+        Some(container) => action_elements(&container, "input", "seek")
+            .filter_map(|e| e.dyn_into::<HtmlInputElement>().ok())
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+/// # Description
+/// the record button of a `.recorder_button` container, once it has been pressed
+fn record_button(container: Option<&Element>) -> Option<HtmlButtonElement> {
+    container?
+        .query_selector("button[data-state]")
+        .ok()
+        .flatten()
+        .and_then(|e| e.dyn_into::<HtmlButtonElement>().ok())
+}
+
+/// # Description
+/// moves playback when a `data-action="seek"` range input is dragged
+fn on_seek(handle: &Rc<RefCell<RecorderState>>, event: &Event) -> Result<(), RecorderError> {
+    let input = event
+        .current_target()
+        .and_then(|t| t.dyn_into::<HtmlInputElement>().ok())
+        .ok_or_else(|| RecorderError::Dom("seek handler bound to a non-input".to_string()))?;
+    let container = match input.closest(".recorder_button").ok().flatten() {
+        Some(container) => container,
+        None => return Ok(()),
+    };
+    let record_state =
+        match record_button(Some(&container)).and_then(|b| b.get_attribute("data-state")) {
+            Some(data) => RecordState::from_string(&data)?,
+            None => return Ok(()),
+        };
+
+    seek_preview(
+        handle,
+        record_state.processing_node,
+        input.value_as_number(),
+    )?;
+    let playback = handle.borrow().playback(record_state.processing_node);
+    show_playback(&container, playback);
+    Ok(())
+}
+
+/// # Description
+/// stops recording and plays `session` back from where its preview is at
+fn start_preview(handle: &Rc<RefCell<RecorderState>>, session: Ptr) -> Result<(), RecorderError> {
+    change_status(handle, session, RecordStatus::Stopped)?;
+    let mut state = handle.borrow_mut();
+    let (ctx, preview) = state.preview_mut(session)?;
+    preview.play(ctx)
+}
+
+fn stop_preview(handle: &Rc<RefCell<RecorderState>>, session: Ptr) {
+    let mut state = handle.borrow_mut();
+    let RecorderState {
+        ctx,
+        processor_list,
+        ..
+    } = &mut *state;
+    if let Some(preview) = processor_list
+        .get_mut(session)
+        .and_then(|node| node.data_mut())
+        .and_then(|session| session.preview.as_mut())
+    {
+        preview.stop(ctx);
+    }
+}
+
+/// # Description
+/// moves the preview of `session` to `position`
+/// ## Comments
+/// - ignored while recording, the preview would be outdated right away
+fn seek_preview(
+    handle: &Rc<RefCell<RecorderState>>,
+    session: Ptr,
+    position: f64,
+) -> Result<(), RecorderError> {
+    let mut state = handle.borrow_mut();
+    if state.session_mut(session)?.status != RecordStatus::Stopped {
+        return Ok(());
+    }
+    let (ctx, preview) = state.preview_mut(session)?;
+    preview.seek(ctx, position)
+}
+
+/// # Description
+/// plays `session` back, or stops it when it's already playing
+/// ## Comments
+/// - the controls in `container` follow playback until it stops
+fn toggle_preview(
+    handle: &Rc<RefCell<RecorderState>>,
+    session: Ptr,
+    container: Option<&Element>,
+) -> Result<(), RecorderError> {
+    let playing = handle
+        .borrow()
+        .playback(session)
+        .is_some_and(|playback| playback.playing);
+    if playing {
+        stop_preview(handle, session);
+        if let Some(container) = container {
+            show_playback(container, handle.borrow().playback(session));
+        }
+        return Ok(());
+    }
+
+    start_preview(handle, session)?;
+    if let Some(container) = container {
+        spawn_local(follow_playback(
+            Rc::downgrade(handle),
+            session,
+            container.clone(),
+        ));
+    }
+    Ok(())
+}
+
+/// # Description
+/// keeps the playback controls in `container` up to date until playback stops
+async fn follow_playback(handle: Weak<RefCell<RecorderState>>, session: Ptr, container: Element) {
+    loop {
+        let playback = handle
+            .upgrade()
+            .and_then(|handle| handle.borrow().playback(session));
+        show_playback(&container, playback);
+        if !playback.is_some_and(|playback| playback.playing) {
+            return;
+        }
+        web_utils::sleep(PLAYBACK_REFRESH_IN_MILLIS).await;
+    }
+}
+
+/// # Description
+/// reflects `playback` on the controls of a recorder
+/// ## Comments
+/// - `data-action="play"` buttons get a `data-playing` attribute while playing
+/// - `data-action="seek"` range inputs span the recording and follow the position
+fn show_playback(container: &Element, playback: Option<PlaybackStatus>) {
+    let playing = playback.is_some_and(|playback| playback.playing);
+    for button in action_elements(container, "button", "play") {
+        if playing {
+            button.set_attribute("data-playing", "").ok();
+        } else {
+            button.remove_attribute("data-playing").ok();
+        }
+    }
+    for input in action_elements(container, "input", "seek")
+        .filter_map(|e| e.dyn_into::<HtmlInputElement>().ok())
+    {
+        let (position, duration) = playback.map_or((0.0, 0.0), |p| (p.position, p.duration));
+        input.set_max(&duration.to_string());
+        input.set_step("any");
+        input.set_value_as_number(position);
+    }
+}
+
+/// # Description
+/// every `tag` in `container` marked with `data-action="<action>"`
+fn action_elements(container: &Element, tag: &str, action: &str) -> impl Iterator<Item = Element> {
+    container
+        .query_selector_all(&format!("{}[data-action=\"{}\"]", tag, action))
+        .map(NodeIter::new)
+        .into_iter()
+        .flatten()
+        .filter_map(|node| node.dyn_into::<Element>().ok())
+}
+
+/// # Description
+/// has the browser save `session`'s recording so far as a wav file
+fn download_recording(
+    handle: &Rc<RefCell<RecorderState>>,
+    session: Ptr,
+) -> Result<(), RecorderError> {
+    let (wav, file_name) = {
+        let mut state = handle.borrow_mut();
+        let session = state.session_mut(session)?;
+        (session.export_wav()?, session.wav_file_name())
+    };
+    download_blob(&bytes_to_blob_with_type(&wav, WAV_MIME_TYPE), &file_name)
+        .map_err(RecorderError::dom)
+}
+
+/// # Description
+/// whether the form `element` sits in is being submitted
+fn is_uploading(element: &Element) -> bool {
+    element
+        .closest("form")
+        .ok()
+        .flatten()
+        .and_then(|form| form.get_attribute(UPLOAD_STATE_ATTRIBUTE))
+        .as_deref()
+        == Some("uploading")
+}
+
+fn on_submit(handle: &Rc<RefCell<RecorderState>>, e: &FocusEvent) -> Result<(), RecorderError> {
+    console::log_1(&"submitting data".into());
+    e.prevent_default();
+
+    let form = e
+        .current_target()
+        .and_then(|e| e.dyn_into::<HtmlFormElement>().ok())
+        .ok_or_else(|| RecorderError::Dom("submit handler bound to a non-form".to_string()))?;
+    if is_uploading(&form) {
+        return Ok(());
+    }
+    let form_data = FormData::new_with_form(&form).map_err(RecorderError::dom)?;
+
+    // every recorder in this form uploads its own recording
+    let mut uploads = Vec::new();
+    let recorders = DomIter::by_class_name(&form, "recorder_button")
+        .flat_map(|container| DomIter::by_tag_name(container, "button"))
+        .filter_map(|e| e.dyn_into::<HtmlButtonElement>().ok())
+        .filter_map(|button| {
+            let data = button.get_attribute("data-state")?;
+            Some((button, data))
+        })
+        .map(|(button, data)| Ok((button, RecordState::from_string(&data)?)))
+        .collect::<Result<Vec<_>, RecorderError>>()?;
+    for (button, record_state) in &recorders {
+        let session = record_state.processing_node;
+        let status = change_status(handle, session, RecordStatus::Stopped)?;
+        button.remove_attribute("style").ok();
+        button
+            .set_attribute(
+                "data-state",
+                &RecordState {
+                    status,
+                    ..*record_state
+                }
+                .to_string(),
+            )
+            .map_err(RecorderError::dom)?;
+
+        // recordings stay with their recorder until the server has them
+        let mut state = handle.borrow_mut();
+        let session = state.session_mut(session)?;
+        match session.uploader.take() {
+            // most of a chunked recording is on the server already, should finishing it fail
+            // the next submit sends the whole recording instead
+            Some(uploader) => uploads.push((session.field_name.clone(), uploader)),
+            None => {
+                let blob = bytes_to_blob(&session.recording()?);
+                form_data
+                    .append_with_blob_and_filename(&session.field_name, &blob, &session.file_name)
+                    .map_err(RecorderError::dom)?;
+            }
+        }
+    }
+
+    form.set_attribute(UPLOAD_STATE_ATTRIBUTE, "uploading")
+        .map_err(RecorderError::dom)?;
+    form.style().set_property(PROGRESS_PROPERTY, "0").ok();
+    spawn_local(submit_form(
+        Rc::downgrade(handle),
+        form,
+        form_data,
+        uploads,
+        recorders,
+    ));
+    Ok(())
+}
+
+/// # Description
+/// sends a form along with its recordings and tells the page how it went
+/// ## Comments
+/// - the form's `data-upload-state` is `uploading`, then `done`, `queued` or `failed`
+/// - once the server has the recordings their recorders start over and a `recordersubmit` event
+///   carrying the server's answer is dispatched on the form
+/// - a form the server couldn't be reached for is kept in the `OfflineQueue`, see `queue_form`
+/// - the page only navigates away when the form asks for it with `data-navigate`
+async fn submit_form(
+    handle: Weak<RefCell<RecorderState>>,
+    form: HtmlFormElement,
+    form_data: FormData,
+    uploads: Vec<(String, ChunkUploader)>,
+    recorders: Vec<(HtmlButtonElement, RecordState)>,
+) {
+    let sent = match finish_uploads(&form_data, uploads).await {
+        Ok(_) => post_form(handle.clone(), &form, &form_data).await,
+        Err(err) => Err(err),
+    };
+    let (status, response) = match sent {
+        Ok((status, response)) if (200..300).contains(&status) => (status, response),
+        Ok((status, response)) => {
+            form.set_attribute(UPLOAD_STATE_ATTRIBUTE, "failed").ok();
+            let err =
+                RecorderError::Upload(format!("server answered with {}: {}", status, response));
+            report_error(&form, &err);
+            return;
+        }
+        Err(err) => {
+            queue_form(handle, form, recorders, err).await;
+            return;
+        }
+    };
+
+    release_recorders(&handle, recorders);
+    form.set_attribute(UPLOAD_STATE_ATTRIBUTE, "done").ok();
+
+    let detail = Object::new();
+    Reflect::set(&detail, &"status".into(), &JsValue::from(status)).ok();
+    Reflect::set(&detail, &"response".into(), &JsValue::from_str(&response)).ok();
+    dispatch_detail(&form, SUBMIT_EVENT, &detail);
+
+    navigate_after_submit(&form);
+}
+
+/// # Description
+/// keeps a form the server couldn't be reached for in the `OfflineQueue`
+/// ## Comments
+/// - once it's stored its recorders start over, the form's `data-upload-state` becomes `queued` and
+///   a `recorderqueued` event carrying the `id` it was queued as and the `reason` is dispatched on it
+/// - should the browser be unable to store it the form failed, the recordings stay with their recorders
+async fn queue_form(
+    handle: Weak<RefCell<RecorderState>>,
+    form: HtmlFormElement,
+    recorders: Vec<(HtmlButtonElement, RecordState)>,
+    err: RecorderError,
+) {
+    let queued = match offline_form_data(&handle, &form, &recorders) {
+        Ok(form_data) => match OfflineQueue::open().await {
+            Ok(queue) => queue.queue_form(&form.action(), &form_data).await,
+            Err(err) => Err(err),
+        },
+        Err(err) => Err(err),
+    };
+    let queued = match queued {
+        Ok(queued) => queued,
+        Err(queue_err) => {
+            console::error_1(&queue_err.to_string().into());
+            form.set_attribute(UPLOAD_STATE_ATTRIBUTE, "failed").ok();
+            report_error(&form, &err);
+            return;
+        }
+    };
+
+    release_recorders(&handle, recorders);
+    form.set_attribute(UPLOAD_STATE_ATTRIBUTE, "queued").ok();
+
+    let detail = Object::new();
+    Reflect::set(&detail, &"id".into(), &JsValue::from_str(&queued.id)).ok();
+    Reflect::set(
+        &detail,
+        &"reason".into(),
+        &JsValue::from_str(&err.to_string()),
+    )
+    .ok();
+    dispatch_detail(&form, QUEUED_EVENT, &detail);
+}
+
+/// # Description
+/// the form along with the whole recording of every recorder in it
+/// ## Comments
+/// - chunked recordings are included as well, a queued form never depends on an upload session
+fn offline_form_data(
+    handle: &Weak<RefCell<RecorderState>>,
+    form: &HtmlFormElement,
+    recorders: &[(HtmlButtonElement, RecordState)],
+) -> Result<FormData, RecorderError> {
+    let handle = handle.upgrade().ok_or_else(unknown_session)?;
+    let mut state = handle.borrow_mut();
+    let form_data = FormData::new_with_form(form).map_err(RecorderError::dom)?;
+    for (_, record_state) in recorders {
+        let session = state.session_mut(record_state.processing_node)?;
+        let blob = bytes_to_blob(&session.recording()?);
+        form_data
+            .append_with_blob_and_filename(&session.field_name, &blob, &session.file_name)
+            .map_err(RecorderError::dom)?;
+    }
+    Ok(form_data)
+}
+
+/// # Description
+/// lets go of the sessions of recorders whose recordings are taken care of
+/// ## Comments
+/// - sessions are stopped on the way out, nothing may still be recording into them
+fn release_recorders(
+    handle: &Weak<RefCell<RecorderState>>,
+    recorders: Vec<(HtmlButtonElement, RecordState)>,
+) {
+    for (button, record_state) in recorders {
+        let removed = handle.upgrade().and_then(|handle| {
+            handle
+                .borrow_mut()
+                .processor_list
+                .remove_at(record_state.processing_node)
+        });
+        if let Some(mut session) = removed {
+            session.stop();
+            session.clear_autosave();
+        }
+        button.remove_attribute("data-state").ok();
+        if let Some(container) = button.closest(".recorder_button").ok().flatten() {
+            show_playback(&container, None);
+        }
+    }
+    if let Some(handle) = handle.upgrade() {
+        release_when_idle(&handle);
+    }
+}
+
+/// # Description
+/// dispatches a bubbling `CustomEvent` named `name` on `target`
+fn dispatch_detail(target: &Element, name: &str, detail: &Object) {
+    let init = CustomEventInit::new();
+    init.set_bubbles(true);
+    init.set_detail(detail);
+    if let Ok(event) = CustomEvent::new_with_event_init_dict(name, &init) {
+        target.dispatch_event(&event).ok();
+    }
+}
+
+/// # Description
+/// finishes chunked uploads, the form carries the id each recording was stored under
+/// ## Comments
+/// - ids go in a field of their own, see `recording_id_field`, so the server never has to
+///   guess which text fields refer to a recording
+async fn finish_uploads(
+    form_data: &FormData,
+    uploads: Vec<(String, ChunkUploader)>,
+) -> Result<(), RecorderError> {
+    for (field_name, uploader) in uploads {
+        let id = uploader.finish().await?;
+        form_data
+            .append_with_str(&recording_id_field(&field_name), &id)
+            .map_err(RecorderError::dom)?;
+    }
+    Ok(())
+}
+
+/// # Description
+/// posts `form_data` to the form's action, failures that may go away on their own are retried
+/// ## Returns
+/// the status and body of the server's final answer, an error when the server couldn't be reached
+/// (or kept failing)
+async fn post_form(
+    handle: Weak<RefCell<RecorderState>>,
+    form: &HtmlFormElement,
+    form_data: &FormData,
+) -> Result<(u16, String), RecorderError> {
+    let mut failures = 0;
+    loop {
+        let (progress_handle, progress_form) = (handle.clone(), form.clone());
+        let on_progress = move |sent, total| {
+            show_upload_progress(
+                &progress_handle,
+                &progress_form,
+                UploadProgress { sent, total },
+            )
+        };
+        let err = match web_utils::send_form_data(&form.action(), form_data, on_progress).await {
+            Ok((status, response)) if !is_transient_status(status) => {
+                return Ok((status, response))
+            }
+            Ok((status, _)) => RecorderError::Upload(format!("server answered with {}", status)),
+            Err(err) => RecorderError::upload(err),
+        };
+        failures += 1;
+        if failures >= MAX_SUBMIT_ATTEMPTS {
+            return Err(err);
+        }
+        console::log_1(&format!("submit failed ({}), retrying", err).into());
+        web_utils::sleep(retry_delay_in_millis(failures)).await;
+    }
+}
+
+/// # Description
+/// tells the page how far along the upload of `form` is
+/// ## Comments
+/// - the form's `--upload-progress` css property goes from `0` to `1`
+/// - a bubbling `recorderprogress` event with the bytes `sent` and the `total` is dispatched on the form
+fn show_upload_progress(
+    handle: &Weak<RefCell<RecorderState>>,
+    form: &HtmlFormElement,
+    progress: UploadProgress,
+) {
+    form.style()
+        .set_property(PROGRESS_PROPERTY, &format!("{:.3}", progress.fraction()))
+        .ok();
+
+    let detail = Object::new();
+    Reflect::set(&detail, &"sent".into(), &JsValue::from(progress.sent)).ok();
+    Reflect::set(&detail, &"total".into(), &JsValue::from(progress.total)).ok();
+    dispatch_detail(form, PROGRESS_EVENT, &detail);
+
+    let listener = handle
+        .upgrade()
+        .and_then(|handle| handle.borrow().progress_listener.clone());
+    if let Some(listener) = listener {
+        listener(form, progress);
+    }
+}
+
+/// # Description
+/// follows the form's `data-navigate` after a successful submit
+/// ## Comments
+/// - `data-navigate="reload"` (or an empty value) reloads the page, any other value is a url to go to
+/// - without the attribute the page stays as it is
+fn navigate_after_submit(form: &HtmlFormElement) {
+    let target = match form.get_attribute("data-navigate") {
+        Some(target) => target,
+        None => return,
+    };
+    if let Some(window) = web_sys::window() {
+        let location = window.location();
+        match target.as_str() {
+            "" | "reload" => location.reload().ok(),
+            url => location.set_href(url).ok(),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use adhoc_audio::Streamable;
+
+    #[test]
+    fn record_state_round_trips_through_its_attribute() {
+        let mut sessions = LinkedList::new();
+        sessions.push_front(0u8);
+        let state = RecordState {
+            status: RecordStatus::Paused,
+            processing_node: sessions.front(),
+            autosave: Some(42),
+        };
+        let parsed = RecordState::from_string(&state.to_string()).unwrap();
+        assert!(parsed.status == RecordStatus::Paused);
+        assert_eq!(parsed.processing_node, state.processing_node);
+        assert_eq!(parsed.autosave(), Some(42));
+    }
+
+    #[test]
+    fn malformed_record_state_is_an_error() {
+        for attribute in ["not base64!", "AAAA", ""] {
+            let err = RecordState::from_string(attribute).err().unwrap();
+            assert_eq!(err.kind(), "malformed-state");
+        }
+    }
+
+    #[test]
+    fn wav_exports_leave_the_recording_running() {
+        let info = StreamInfo::new(8_000, 1);
+        let mut session = RecorderSession {
+            codec: new_codec(info),
+            info,
+            status: RecordStatus::Recording,
+            field_name: DEFAULT_FIELD_NAME.to_string(),
+            file_name: "take.v2.adhoc".to_string(),
+            beep: false,
+            capture: None,
+            uploader: None,
+            level_listener: None,
+            status_listener: None,
+            preview: None,
+            autosave: None,
+            voice: None,
+            voice_listener: None,
+            trim: None,
+            gate: RecordGate::new(info.channels()),
+            warmup_samples: 0,
+        };
+        session.codec.encode(&[0.5; 4_000]);
+
+        let wav = session.export_wav().expect("export failed");
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(&wav[8..12], b"WAVE");
+        assert_eq!(wav.len(), 44 + 4_000 * 2);
+        assert_eq!(session.wav_file_name(), "take.v2.wav");
+
+        session.codec.encode(&[0.5; 4_000]);
+        assert_eq!(session.export_wav().unwrap().len(), 44 + 8_000 * 2);
+    }
+}
@@ -0,0 +1,217 @@
+use super::RecorderError;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use wasm_bindgen::JsValue;
+use web_sys::MediaStreamTrack;
+
+/// # Description
+/// What the microphone is asked for, and what the browser may do to its signal before it gets
+/// recorded
+/// ## Comments
+/// - a field left at `None` keeps the browser's default, which has echo cancellation, noise
+///   suppression and auto gain switched on in most browsers
+/// - those defaults suit speech, use `CaptureConstraints::unprocessed` for music
+/// - browsers take everything but the device as a wish, `CaptureSettings` tells what they did
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CaptureConstraints {
+    pub echo_cancellation: Option<bool>,
+    pub noise_suppression: Option<bool>,
+    pub auto_gain_control: Option<bool>,
+    pub channel_count: Option<u32>,
+    /// rate the microphone should run at, recordings are still resampled to their session's rate
+    pub sample_rate: Option<u32>,
+    /// in seconds
+    pub latency: Option<f64>,
+}
+
+impl CaptureConstraints {
+    /// # Description
+    /// the signal as the microphone delivers it, every bit of browser processing switched off
+    pub fn unprocessed() -> Self {
+        Self::default()
+            .with_echo_cancellation(Some(false))
+            .with_noise_suppression(Some(false))
+            .with_auto_gain_control(Some(false))
+    }
+
+    /// # Description
+    /// reads constraints spelled the way `getUserMedia` spells them, like
+    /// `{"echoCancellation":false}`
+    pub fn from_json(constraints: &str) -> Result<Self, RecorderError> {
+        serde_json::from_str(constraints)
+            .map_err(|err| RecorderError::Audio(format!("unreadable capture constraints: {}", err)))
+    }
+
+    pub fn with_echo_cancellation(mut self, echo_cancellation: Option<bool>) -> Self {
+        self.echo_cancellation = echo_cancellation;
+        self
+    }
+
+    pub fn with_noise_suppression(mut self, noise_suppression: Option<bool>) -> Self {
+        self.noise_suppression = noise_suppression;
+        self
+    }
+
+    pub fn with_auto_gain_control(mut self, auto_gain_control: Option<bool>) -> Self {
+        self.auto_gain_control = auto_gain_control;
+        self
+    }
+
+    pub fn with_channel_count(mut self, channel_count: Option<u32>) -> Self {
+        self.channel_count = channel_count;
+        self
+    }
+
+    pub fn with_sample_rate(mut self, sample_rate: Option<u32>) -> Self {
+        self.sample_rate = sample_rate;
+        self
+    }
+
+    pub fn with_latency(mut self, latency: Option<f64>) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// # Description
+    /// the `audio` member of the constraints `getUserMedia` gets called with
+    /// ## Parameters
+    /// - `device_id` is the microphone to open, `None` for the browser's default
+    /// ## Comments
+    /// - only the device is required, everything else is `ideal` so a microphone that can't
+    ///   comply still opens
+    pub fn audio_constraints(&self, device_id: Option<&str>) -> Value {
+        let mut audio = Map::new();
+        if let Some(id) = device_id {
+            audio.insert("deviceId".to_string(), json!({ "exact": id }));
+        }
+        let switches = [
+            ("echoCancellation", self.echo_cancellation),
+            ("noiseSuppression", self.noise_suppression),
+            ("autoGainControl", self.auto_gain_control),
+        ];
+        for (name, switch) in switches {
+            if let Some(switch) = switch {
+                audio.insert(name.to_string(), json!({ "ideal": switch }));
+            }
+        }
+        let ranges = [
+            ("channelCount", self.channel_count.map(f64::from)),
+            ("sampleRate", self.sample_rate.map(f64::from)),
+            ("latency", self.latency),
+        ];
+        for (name, value) in ranges {
+            if let Some(value) = value {
+                audio.insert(name.to_string(), json!({ "ideal": value }));
+            }
+        }
+        Value::Object(audio)
+    }
+
+    /// # Description
+    /// the processing switches the browser didn't honor
+    /// ## Returns
+    /// the names of the constraints, as `getUserMedia` knows them
+    /// ## Comments
+    /// - a setting the browser doesn't report can't be told apart from one it honored
+    pub fn unmet(&self, settings: &CaptureSettings) -> Vec<&'static str> {
+        [
+            (
+                "echoCancellation",
+                self.echo_cancellation,
+                settings.echo_cancellation,
+            ),
+            (
+                "noiseSuppression",
+                self.noise_suppression,
+                settings.noise_suppression,
+            ),
+            (
+                "autoGainControl",
+                self.auto_gain_control,
+                settings.auto_gain_control,
+            ),
+        ]
+        .into_iter()
+        .filter(|&(_, wanted, applied)| wanted.is_some() && applied.is_some() && wanted != applied)
+        .map(|(name, _, _)| name)
+        .collect()
+    }
+}
+
+/// # Description
+/// What the browser actually applied to the microphone being recorded from
+/// ## Comments
+/// - `None` wherever the browser doesn't say
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CaptureSettings {
+    pub device_id: Option<String>,
+    pub echo_cancellation: Option<bool>,
+    pub noise_suppression: Option<bool>,
+    pub auto_gain_control: Option<bool>,
+    pub channel_count: Option<u32>,
+    pub sample_rate: Option<u32>,
+    /// in seconds
+    pub latency: Option<f64>,
+}
+
+impl CaptureSettings {
+    /// # Description
+    /// reads the settings of `track` as `getSettings` reports them
+    pub fn of_track(track: &MediaStreamTrack) -> Result<Self, RecorderError> {
+        let settings = js_sys::JSON::stringify(&track.get_settings())
+            .map_err(RecorderError::audio)?
+            .as_string()
+            .unwrap_or_default();
+        Self::from_json(&settings)
+    }
+
+    pub fn from_json(settings: &str) -> Result<Self, RecorderError> {
+        serde_json::from_str(settings)
+            .map_err(|err| RecorderError::Audio(format!("unreadable capture settings: {}", err)))
+    }
+}
+
+/// # Description
+/// hands a json value over to javascript
+pub(crate) fn json_to_js(value: &Value) -> Result<JsValue, RecorderError> {
+    js_sys::JSON::parse(&value.to_string()).map_err(RecorderError::audio)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unprocessed_capture_asks_for_every_switch_to_be_off() {
+        let constraints = CaptureConstraints::unprocessed()
+            .with_channel_count(Some(2))
+            .with_latency(Some(0.01));
+        assert_eq!(
+            constraints.audio_constraints(Some("usb-interface")),
+            json!({
+                "deviceId": { "exact": "usb-interface" },
+                "echoCancellation": { "ideal": false },
+                "noiseSuppression": { "ideal": false },
+                "autoGainControl": { "ideal": false },
+                "channelCount": { "ideal": 2.0 },
+                "latency": { "ideal": 0.01 },
+            })
+        );
+        assert_eq!(
+            CaptureConstraints::default().audio_constraints(None),
+            json!({})
+        );
+
+        // chrome reports more than we ask about, and safari leaves some switches out
+        let settings = CaptureSettings::from_json(
+            r#"{"deviceId":"usb-interface","groupId":"g","echoCancellation":true,
+                "autoGainControl":false,"channelCount":2,"sampleRate":48000}"#,
+        )
+        .unwrap();
+        assert_eq!(settings.sample_rate, Some(48000));
+        assert_eq!(settings.noise_suppression, None);
+        assert_eq!(constraints.unmet(&settings), vec!["echoCancellation"]);
+    }
+}
@@ -0,0 +1,203 @@
+use super::{json_to_js, CaptureConstraints, RecorderError};
+use js_sys::{Array, Object, Reflect};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    MediaDeviceInfo, MediaDeviceKind, MediaDevices, MediaStream, MediaStreamConstraints,
+    PermissionState, PermissionStatus, Storage,
+};
+
+/// local storage key the picked microphone is remembered under
+const INPUT_DEVICE_KEY: &str = "audio_recorder.input_device";
+/// id browsers give the pseudo device that follows the system's default microphone
+const DEFAULT_DEVICE_ID: &str = "default";
+
+/// # Description
+/// a microphone the browser can record from
+#[derive(Clone, Debug, PartialEq)]
+pub struct InputDevice {
+    pub id: String,
+    /// empty until the page has been granted microphone access
+    pub label: String,
+    /// devices sharing a group sit in the same piece of hardware, like a headset
+    pub group_id: String,
+}
+
+/// # Description
+/// whether the page may record from the microphone
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MicrophonePermission {
+    Granted,
+    /// the user (or the browser) blocked the microphone, nothing can be recorded until it's allowed
+    /// again in the browser's settings
+    Denied,
+    /// the user gets asked the first time something is recorded
+    Prompt,
+    /// the browser won't tell before the microphone is asked for
+    Unknown,
+}
+
+impl MicrophonePermission {
+    /// # Description
+    /// how the permission is spelled for the page, see `Recorder`
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Granted => "granted",
+            Self::Denied => "denied",
+            Self::Prompt => "prompt",
+            Self::Unknown => "unknown",
+        }
+    }
+}
+
+impl From<PermissionState> for MicrophonePermission {
+    fn from(state: PermissionState) -> Self {
+        match state {
+            PermissionState::Granted => Self::Granted,
+            PermissionState::Denied => Self::Denied,
+            PermissionState::Prompt => Self::Prompt,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// # Description
+/// the microphone's entry in the permissions api, it follows the user changing their mind
+/// ## Returns
+/// `None` where the browser can't be asked about the microphone
+pub async fn microphone_permission_status() -> Option<PermissionStatus> {
+    let descriptor = Object::new();
+    Reflect::set(&descriptor, &"name".into(), &"microphone".into()).ok()?;
+    let query = web_sys::window()?
+        .navigator()
+        .permissions()
+        .ok()?
+        .query(&descriptor)
+        .ok()?;
+    JsFuture::from(query).await.ok()?.dyn_into().ok()
+}
+
+/// # Description
+/// whether the page may record from the microphone, asking doesn't prompt the user
+pub async fn microphone_permission() -> MicrophonePermission {
+    microphone_permission_status()
+        .await
+        .map_or(MicrophonePermission::Unknown, |status| {
+            status.state().into()
+        })
+}
+
+/// # Description
+/// the microphone to record from, out of the one that was picked and the ones plugged in
+/// ## Returns
+/// `None` for the browser's default microphone
+/// ## Comments
+/// - falls back to the default when the picked microphone isn't plugged in
+pub fn pick_input(preferred: Option<&str>, devices: &[InputDevice]) -> Option<String> {
+    preferred
+        .filter(|&id| id != DEFAULT_DEVICE_ID)
+        .filter(|&id| devices.iter().any(|device| device.id == id))
+        .map(String::from)
+}
+
+pub fn media_devices() -> Result<MediaDevices, RecorderError> {
+    web_sys::window()
+        .ok_or_else(|| RecorderError::Dom("no window".to_string()))?
+        .navigator()
+        .media_devices()
+        .map_err(|_| RecorderError::NoInputDevice)
+}
+
+/// # Description
+/// every microphone the browser knows about
+pub async fn input_devices() -> Result<Vec<InputDevice>, RecorderError> {
+    let devices = JsFuture::from(
+        media_devices()?
+            .enumerate_devices()
+            .map_err(RecorderError::audio)?,
+    )
+    .await
+    .map_err(RecorderError::audio)?;
+    Ok(Array::from(&devices)
+        .iter()
+        .filter_map(|device| device.dyn_into::<MediaDeviceInfo>().ok())
+        .filter(|device| device.kind() == MediaDeviceKind::Audioinput)
+        .map(|device| InputDevice {
+            id: device.device_id(),
+            label: device.label(),
+            group_id: device.group_id(),
+        })
+        .collect())
+}
+
+/// # Description
+/// asks for a stream off the microphone `device_id` names, or off the default one
+/// ## Comments
+/// - `constraints` say what the browser may do to the signal, see `CaptureConstraints`
+pub async fn open_input(
+    device_id: Option<&str>,
+    constraints: &CaptureConstraints,
+) -> Result<MediaStream, RecorderError> {
+    let audio = json_to_js(&constraints.audio_constraints(device_id))?;
+    let media_constraints = MediaStreamConstraints::new();
+    media_constraints.set_audio(&audio);
+    media_constraints.set_video(&JsValue::from_bool(false));
+    JsFuture::from(
+        media_devices()?
+            .get_user_media_with_constraints(&media_constraints)
+            .map_err(RecorderError::from_user_media)?,
+    )
+    .await
+    .map_err(RecorderError::from_user_media)?
+    .dyn_into::<MediaStream>()
+    .map_err(RecorderError::audio)
+}
+
+/// # Description
+/// the microphone picked on an earlier visit, `None` when the default was used
+pub fn preferred_input() -> Option<String> {
+    local_storage()?
+        .get_item(INPUT_DEVICE_KEY)
+        .ok()
+        .flatten()
+        .filter(|id| !id.is_empty())
+}
+
+/// # Description
+/// remembers the microphone picked for later visits, `None` goes back to the default
+pub fn set_preferred_input(device_id: Option<&str>) {
+    if let Some(storage) = local_storage() {
+        match device_id {
+            Some(id) => storage.set_item(INPUT_DEVICE_KEY, id).ok(),
+            None => storage.remove_item(INPUT_DEVICE_KEY).ok(),
+        };
+    }
+}
+
+fn local_storage() -> Option<Storage> {
+    web_sys::window()?.local_storage().ok().flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unplugged_microphones_fall_back_to_the_default() {
+        let devices = ["default", "usb-headset", "built-in"]
+            .iter()
+            .map(|&id| InputDevice {
+                id: id.to_string(),
+                label: String::new(),
+                group_id: String::new(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            pick_input(Some("usb-headset"), &devices),
+            Some("usb-headset".to_string())
+        );
+        assert_eq!(pick_input(Some("bluetooth"), &devices), None);
+        assert_eq!(pick_input(Some("default"), &devices), None);
+        assert_eq!(pick_input(None, &devices), None);
+    }
+}
@@ -0,0 +1,123 @@
+use js_sys::{Object, Reflect};
+use std::fmt;
+use wasm_bindgen::{JsCast, JsValue};
+
+/// # Description
+/// Everything that can go wrong while recording or uploading
+/// ## Comments
+/// - errors are surfaced to the page as a `recordererror` event and a message next to the recorder
+#[derive(Clone, Debug, PartialEq)]
+pub enum RecorderError {
+    /// the user (or the browser) refused microphone access
+    PermissionDenied,
+    /// there is no microphone to record from
+    NoInputDevice,
+    /// a recording couldn't be encoded or decoded
+    Codec(String),
+    /// a recorder's `data-state` (or a session it points to) is unusable
+    MalformedState(String),
+    /// the recording couldn't be sent to the server
+    Upload(String),
+    /// the browser couldn't keep a recording around for later, see `offline`
+    Storage(String),
+    /// the recorder sits in markup it can't work with, like a button outside of a form
+    Dom(String),
+    /// webaudio refused to build the capture graph
+    Audio(String),
+}
+
+impl RecorderError {
+    /// # Description
+    /// classifies a `getUserMedia` rejection
+    pub fn from_user_media(err: JsValue) -> Self {
+        match error_field(&err, "name").as_deref() {
+            Some("NotAllowedError") | Some("SecurityError") | Some("PermissionDeniedError") => {
+                Self::PermissionDenied
+            }
+            Some("NotFoundError") | Some("OverconstrainedError") | Some("DevicesNotFoundError") => {
+                Self::NoInputDevice
+            }
+            _ => Self::Audio(error_message(&err)),
+        }
+    }
+
+    pub fn audio(err: JsValue) -> Self {
+        Self::Audio(error_message(&err))
+    }
+
+    pub fn dom(err: JsValue) -> Self {
+        Self::Dom(error_message(&err))
+    }
+
+    pub fn upload(err: JsValue) -> Self {
+        Self::Upload(error_message(&err))
+    }
+
+    pub fn storage(err: JsValue) -> Self {
+        Self::Storage(error_message(&err))
+    }
+
+    /// # Description
+    /// short machine readable name of the error, handed to the page as `kind`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::PermissionDenied => "permission-denied",
+            Self::NoInputDevice => "no-input-device",
+            Self::Codec(_) => "codec",
+            Self::MalformedState(_) => "malformed-state",
+            Self::Upload(_) => "upload",
+            Self::Storage(_) => "storage",
+            Self::Dom(_) => "dom",
+            Self::Audio(_) => "audio",
+        }
+    }
+}
+
+impl fmt::Display for RecorderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PermissionDenied => write!(f, "microphone access was denied"),
+            Self::NoInputDevice => write!(f, "no microphone was found"),
+            Self::Codec(msg) => write!(f, "recording could not be encoded: {}", msg),
+            Self::MalformedState(msg) => write!(f, "recorder state is malformed: {}", msg),
+            Self::Upload(msg) => write!(f, "recording could not be uploaded: {}", msg),
+            Self::Storage(msg) => write!(f, "recording could not be stored: {}", msg),
+            Self::Dom(msg) => write!(f, "recorder markup is invalid: {}", msg),
+            Self::Audio(msg) => write!(f, "audio capture failed: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for RecorderError {}
+
+/// # Description
+/// javascript sees a `RecorderError` as an `Error` with an extra `kind` field
+impl From<RecorderError> for JsValue {
+    fn from(err: RecorderError) -> Self {
+        let js_err = js_sys::Error::new(&err.to_string());
+        js_err.set_name("RecorderError");
+        Reflect::set(&js_err, &"kind".into(), &err.kind().into()).ok();
+        js_err.into()
+    }
+}
+
+fn error_field(err: &JsValue, field: &str) -> Option<String> {
+    if !err.is_object() {
+        return None;
+    }
+    Reflect::get(err, &field.into())
+        .ok()
+        .and_then(|value| value.as_string())
+}
+
+/// # Description
+/// best effort description of whatever a web api threw
+fn error_message(err: &JsValue) -> String {
+    err.as_string()
+        .or_else(|| error_field(err, "message"))
+        .or_else(|| {
+            err.dyn_ref::<Object>()
+                .map(|obj| String::from(obj.to_string()))
+        })
+        .unwrap_or_else(|| "unknown error".to_string())
+}
@@ -0,0 +1,141 @@
+use super::RecorderError;
+use adhoc_audio::StreamInfo;
+use wasm_bindgen::JsCast;
+use web_sys::{
+    AudioBuffer, AudioBufferSourceNode, AudioContext, AudioNode, AudioScheduledSourceNode,
+};
+
+/// # Description
+/// where the preview of a recording is at
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct PlaybackStatus {
+    /// in seconds
+    pub position: f64,
+    /// in seconds
+    pub duration: f64,
+    pub playing: bool,
+}
+
+/// # Description
+/// Plays a recording back through an `AudioBufferSourceNode`, the same way beeps are played
+/// ## Comments
+/// - the recording is decoded into an `AudioBuffer` once, every play gets a new source node
+///   because source nodes can only be started once
+/// - positions are in seconds
+pub struct Preview {
+    buffer: AudioBuffer,
+    source: Option<AudioBufferSourceNode>,
+    /// context time the recording would have started at had it been played from the start
+    started_at: f64,
+    /// where playback resumes from while stopped
+    position: f64,
+}
+
+impl Preview {
+    /// # Description
+    /// uploads decoded audio into an `AudioBuffer`
+    /// ## Parameters
+    /// - `channels` holds one buffer per channel, see `recording::decode_planar`
+    pub fn new(
+        ctx: &AudioContext,
+        info: StreamInfo,
+        channels: &[Vec<f32>],
+    ) -> Result<Self, RecorderError> {
+        // empty buffers aren't allowed
+        let frames = channels.first().map_or(0, |channel| channel.len()).max(1);
+        let buffer = ctx
+            .create_buffer(
+                channels.len().max(1) as u32,
+                frames as u32,
+                info.frequency() as f32,
+            )
+            .map_err(RecorderError::audio)?;
+        for (k, channel) in channels.iter().enumerate() {
+            buffer
+                .copy_to_channel(channel, k as i32)
+                .map_err(RecorderError::audio)?;
+        }
+        Ok(Self {
+            buffer,
+            source: None,
+            started_at: 0.0,
+            position: 0.0,
+        })
+    }
+
+    pub fn status(&self, ctx: &AudioContext) -> PlaybackStatus {
+        let duration = self.buffer.duration();
+        let position = match self.source {
+            Some(_) => (ctx.current_time() - self.started_at).clamp(0.0, duration),
+            None => self.position,
+        };
+        PlaybackStatus {
+            position,
+            duration,
+            playing: self.source.is_some() && position < duration,
+        }
+    }
+
+    /// # Description
+    /// plays from the current position, or from the start once the end was reached
+    pub fn play(&mut self, ctx: &AudioContext) -> Result<(), RecorderError> {
+        let status = self.status(ctx);
+        self.stop(ctx);
+        let offset = if status.position >= status.duration {
+            0.0
+        } else {
+            status.position
+        };
+
+        let source = AudioBufferSourceNode::new(ctx).map_err(RecorderError::audio)?;
+        source.set_buffer(Some(&self.buffer));
+        source
+            .dyn_ref::<AudioNode>()
+            .unwrap()
+            .connect_with_audio_node(&ctx.destination())
+            .map_err(RecorderError::audio)?;
+        source
+            .start_with_when_and_grain_offset(0.0, offset)
+            .map_err(RecorderError::audio)?;
+        self.started_at = ctx.current_time() - offset;
+        self.source = Some(source);
+        Ok(())
+    }
+
+    /// # Description
+    /// stops playing, the position is kept
+    pub fn stop(&mut self, ctx: &AudioContext) {
+        self.position = self.status(ctx).position;
+        if let Some(source) = self.source.take() {
+            silence(&source);
+        }
+    }
+
+    /// # Description
+    /// moves playback to `position`, playback carries on from there if it was playing
+    pub fn seek(&mut self, ctx: &AudioContext, position: f64) -> Result<(), RecorderError> {
+        let playing = self.status(ctx).playing;
+        self.stop(ctx);
+        self.position = position.clamp(0.0, self.buffer.duration());
+        if playing {
+            self.play(ctx)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Drop for Preview {
+    fn drop(&mut self) {
+        if let Some(source) = self.source.take() {
+            silence(&source);
+        }
+    }
+}
+
+fn silence(source: &AudioBufferSourceNode) {
+    AsRef::<AudioScheduledSourceNode>::as_ref(source)
+        .stop()
+        .ok();
+    source.dyn_ref::<AudioNode>().unwrap().disconnect().ok();
+}
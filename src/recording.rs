@@ -0,0 +1,304 @@
+mod trim;
+
+pub use trim::*;
+
+use adhoc_audio::{AdhocCodec, StreamInfo, Streamable, WavCodec};
+use serde::{Deserialize, Serialize};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+/// size of the header `WavWriter` writes, the pcm data starts right after it
+const WAV_HEADER_SIZE: u32 = 44;
+
+/// # Description
+/// What a stored recording holds
+/// ## Comments
+/// - the upload server writes this next to every recording as a json sidecar
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RecordingInfo {
+    pub duration_in_secs: f64,
+    pub sample_rate: u32,
+    pub channels: u32,
+    /// number of samples per channel
+    pub frames: u64,
+    /// size of the encoded recording in bytes
+    pub size: u64,
+}
+
+impl RecordingInfo {
+    /// # Description
+    /// decodes the whole recording in `bytes`
+    /// ## Comments
+    /// - nothing that fails to decode gets through, so this doubles as validation of untrusted uploads
+    pub fn inspect(bytes: &[u8]) -> Result<Self, &'static str> {
+        let mut codec = AdhocCodec::load(bytes).ok_or("not an adhoc recording")?;
+        let info = codec.info();
+        if info.channels() == 0 || info.frequency() == 0 {
+            return Err("recording has no channels or no sample rate");
+        }
+
+        let mut samples = 0u64;
+        let mut buffer = vec![0.0; 1024 * info.channels()];
+        while let Some(n) = codec.decode(&mut buffer) {
+            samples += n as u64;
+        }
+        let frames = samples / info.channels() as u64;
+
+        Ok(Self {
+            duration_in_secs: frames as f64 / info.frequency() as f64,
+            sample_rate: info.frequency() as u32,
+            channels: info.channels() as u32,
+            frames,
+            size: bytes.len() as u64,
+        })
+    }
+}
+
+/// # Description
+/// Writes 16 bit pcm wav files as samples come in
+/// ## Comments
+/// - samples are converted the same way `WavCodec` does, but nothing is kept in memory
+/// - the sizes in the header are only filled in by `finish`
+pub struct WavWriter<W: Write + Seek> {
+    dst: W,
+    info: StreamInfo,
+    data_size: u32,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    /// # Description
+    /// writes a placeholder header to `dst`
+    pub fn new(mut dst: W, info: StreamInfo) -> io::Result<Self> {
+        dst.write_all(&wav_header(info, 0))?;
+        Ok(Self {
+            dst,
+            info,
+            data_size: 0,
+        })
+    }
+
+    /// # Description
+    /// appends interleaved `samples`, a trailing partial frame is dropped
+    pub fn write(&mut self, samples: &[f32]) -> io::Result<()> {
+        let channels = self.info.channels().max(1);
+        let valid_len = (samples.len() / channels) * channels;
+        let pcm = samples[0..valid_len]
+            .iter()
+            .flat_map(|&sample| ((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16).to_le_bytes())
+            .collect::<Vec<_>>();
+        self.data_size = u32::try_from(pcm.len())
+            .ok()
+            .and_then(|len| self.data_size.checked_add(len))
+            .filter(|&size| size <= u32::MAX - WAV_HEADER_SIZE)
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "recording too long for wav")
+            })?;
+        self.dst.write_all(&pcm)
+    }
+
+    /// # Description
+    /// fills in the header now that the length is known
+    /// ## Returns
+    /// the destination, positioned at the end of the file
+    pub fn finish(mut self) -> io::Result<W> {
+        self.dst.seek(SeekFrom::Start(0))?;
+        self.dst.write_all(&wav_header(self.info, self.data_size))?;
+        self.dst.seek(SeekFrom::End(0))?;
+        self.dst.flush()?;
+        Ok(self.dst)
+    }
+}
+
+fn wav_header(info: StreamInfo, data_size: u32) -> Vec<u8> {
+    let channels = info.channels() as u16;
+    let sample_rate = info.frequency() as u32;
+    let block_align = channels * 2;
+
+    let mut header = Vec::with_capacity(WAV_HEADER_SIZE as usize);
+    header.extend_from_slice(b"RIFF");
+    header.extend_from_slice(&(WAV_HEADER_SIZE - 8 + data_size).to_le_bytes());
+    header.extend_from_slice(b"WAVEfmt ");
+    header.extend_from_slice(&16u32.to_le_bytes());
+    // uncompressed pcm
+    header.extend_from_slice(&1u16.to_le_bytes());
+    header.extend_from_slice(&channels.to_le_bytes());
+    header.extend_from_slice(&sample_rate.to_le_bytes());
+    header.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+    header.extend_from_slice(&block_align.to_le_bytes());
+    header.extend_from_slice(&16u16.to_le_bytes());
+    header.extend_from_slice(b"data");
+    header.extend_from_slice(&data_size.to_le_bytes());
+    header
+}
+
+/// # Description
+/// decodes the adhoc recording read from `src` and writes it to `dst` as a 16 bit pcm wav
+/// ## Comments
+/// - the recording is decoded a block at a time, the decoded audio is never held in memory as a whole
+/// ## Returns
+/// the number of frames written
+pub fn transcode_to_wav<R, W>(src: R, dst: W) -> io::Result<u64>
+where
+    R: Read,
+    W: Write + Seek,
+{
+    let mut codec = AdhocCodec::load(src)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "not an adhoc recording"))?;
+    let info = codec.info();
+    let mut wav = WavWriter::new(dst, info)?;
+    let mut buffer = vec![0.0; 1024 * info.channels().max(1)];
+    let mut samples = 0u64;
+    while let Some(n) = codec.decode(&mut buffer) {
+        wav.write(&buffer[0..n])?;
+        samples += n as u64;
+    }
+    wav.finish()?;
+    Ok(samples / info.channels().max(1) as u64)
+}
+
+/// # Description
+/// encodes the wav read from `src` into an adhoc recording written to `dst`
+/// ## Parameters
+/// - `compression_level` is handed to `AdhocCodec::with_compression_level`, higher is smaller but lossier
+/// ## Returns
+/// the number of frames written
+pub fn transcode_from_wav<R, W>(src: R, dst: W, compression_level: u32) -> io::Result<u64>
+where
+    R: Read + Seek,
+    W: Write,
+{
+    let mut wav =
+        WavCodec::load(src).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    let info = wav.info();
+    let mut codec = AdhocCodec::new()
+        .with_compression_level(compression_level)
+        .with_info(info);
+    let mut buffer = vec![0.0; 1024 * info.channels().max(1)];
+    let mut samples = 0u64;
+    while let Some(n) = wav.decode(&mut buffer) {
+        codec.encode(&buffer[0..n]);
+        samples += n as u64;
+    }
+    codec
+        .save_to(dst)
+        .ok_or_else(|| io::Error::other("recording could not be written"))?;
+    Ok(samples / info.channels().max(1) as u64)
+}
+
+/// # Description
+/// decodes the adhoc recording read from `src` into one buffer per channel
+/// ## Comments
+/// - this is the layout `AudioBuffer`s want, unlike `Streamable` which interleaves
+pub fn decode_planar<R: Read>(src: R) -> io::Result<(StreamInfo, Vec<Vec<f32>>)> {
+    let mut codec = AdhocCodec::load(src)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "not an adhoc recording"))?;
+    let info = codec.info();
+    let channels = info.channels().max(1);
+    let mut planar = vec![Vec::new(); channels];
+    let mut buffer = vec![0.0; 1024 * channels];
+    while let Some(n) = codec.decode(&mut buffer) {
+        for frame in buffer[0..n].chunks_exact(channels) {
+            for (channel, &sample) in planar.iter_mut().zip(frame) {
+                channel.push(sample);
+            }
+        }
+    }
+    Ok((info, planar))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::{record, CaptureSource, SyntheticSource};
+
+    #[test]
+    fn inspecting_a_recording_reports_its_format_and_length() {
+        let mut source = SyntheticSource::sine(440.0, 0.5)
+            .with_sample_rate(16_000)
+            .with_channels(2)
+            .with_duration(1.5);
+        let mut codec = AdhocCodec::new().with_info(source.info());
+        record(&mut source, &mut codec, |_| true);
+        let mut bytes = Vec::new();
+        codec.save_to(&mut bytes).expect("serialization failed");
+
+        let info = RecordingInfo::inspect(&bytes).expect("recording should be valid");
+        assert_eq!(info.sample_rate, 16_000);
+        assert_eq!(info.channels, 2);
+        assert_eq!(info.frames, 24_000);
+        assert!((info.duration_in_secs - 1.5).abs() < 1e-9);
+        assert_eq!(info.size, bytes.len() as u64);
+
+        assert!(RecordingInfo::inspect(b"definitely not audio").is_err());
+        assert!(RecordingInfo::inspect(&bytes[0..bytes.len() / 2]).is_err());
+    }
+
+    #[test]
+    fn recordings_transcode_to_wav_without_changing_their_length() {
+        let mut source = SyntheticSource::sine(440.0, 0.5)
+            .with_sample_rate(8_000)
+            .with_channels(2)
+            .with_duration(2.0);
+        let mut codec = AdhocCodec::new().with_info(source.info());
+        record(&mut source, &mut codec, |_| true);
+        let mut bytes = Vec::new();
+        codec.save_to(&mut bytes).expect("serialization failed");
+
+        let mut wav = io::Cursor::new(Vec::new());
+        let frames = transcode_to_wav(&bytes[..], &mut wav).expect("transcoding failed");
+        assert_eq!(frames, 16_000);
+
+        let wav = wav.into_inner();
+        assert_eq!(wav.len(), WAV_HEADER_SIZE as usize + 16_000 * 2 * 2);
+        let mut decoded = WavCodec::load(io::Cursor::new(wav)).expect("not a valid wav");
+        assert_eq!(decoded.info().frequency(), 8_000);
+        assert_eq!(decoded.info().channels(), 2);
+        let mut buffer = [0.0; 1024];
+        let mut samples = 0;
+        while let Some(n) = decoded.decode(&mut buffer) {
+            samples += n;
+        }
+        assert_eq!(samples, 32_000);
+
+        assert!(transcode_to_wav(&b"not audio"[..], io::Cursor::new(Vec::new())).is_err());
+    }
+
+    #[test]
+    fn wav_files_encode_into_recordings() {
+        let mut source = SyntheticSource::sine(220.0, 0.5)
+            .with_sample_rate(11_025)
+            .with_duration(1.0);
+        let mut wav = io::Cursor::new(Vec::new());
+        let mut writer = WavWriter::new(&mut wav, source.info()).unwrap();
+        let mut block = vec![0.0; source.block_size()];
+        while let Some(n) = source.next_block(&mut block) {
+            writer.write(&block[0..n]).unwrap();
+        }
+        writer.finish().unwrap();
+        wav.set_position(0);
+
+        let mut recording = Vec::new();
+        let frames = transcode_from_wav(wav, &mut recording, 6).expect("encoding failed");
+        assert_eq!(frames, 11_025);
+        let info = RecordingInfo::inspect(&recording).expect("not a valid recording");
+        assert_eq!(info.sample_rate, 11_025);
+        assert_eq!(info.channels, 1);
+        assert_eq!(info.frames, 11_025);
+    }
+
+    #[test]
+    fn planar_decoding_splits_the_channels_apart() {
+        let mut codec = AdhocCodec::new().with_info(StreamInfo::new(8_000, 2));
+        let frames = (0..4_000).flat_map(|_| [0.5, -0.5]).collect::<Vec<f32>>();
+        codec.encode(&frames);
+        let mut bytes = Vec::new();
+        codec.save_to(&mut bytes).expect("serialization failed");
+
+        let (info, planar) = decode_planar(&bytes[..]).expect("decoding failed");
+        assert_eq!(info.channels(), 2);
+        assert_eq!(planar.len(), 2);
+        assert!(planar.iter().all(|channel| channel.len() == 4_000));
+        let mean = |channel: &[f32]| channel.iter().sum::<f32>() / channel.len() as f32;
+        assert!(mean(&planar[0]) > 0.4);
+        assert!(mean(&planar[1]) < -0.4);
+    }
+}
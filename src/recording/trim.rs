@@ -0,0 +1,267 @@
+use crate::math::{VadConfig, VoiceActivityDetector};
+use adhoc_audio::{AdhocCodec, Streamable};
+use std::{
+    io::{self, Read, Write},
+    ops::Range,
+};
+
+/// how much audio the detector is fed at a time, speech is located to within this
+const STEP_IN_SECS: f32 = 0.01;
+
+/// # Description
+/// How much of the silence in a recording `trim_silence` leaves in
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TrimConfig {
+    pub vad: VadConfig,
+    /// silence kept before the first word and after the last one, so words don't get clipped
+    pub padding_in_secs: f32,
+    /// pauses longer than this are shortened to it, `None` keeps every pause
+    pub max_gap_in_secs: Option<f32>,
+    /// length of the fade joining both sides of a shortened pause
+    pub crossfade_in_secs: f32,
+}
+
+impl Default for TrimConfig {
+    fn default() -> Self {
+        Self {
+            vad: VadConfig::default(),
+            padding_in_secs: 0.3,
+            max_gap_in_secs: Some(1.0),
+            crossfade_in_secs: 0.02,
+        }
+    }
+}
+
+impl TrimConfig {
+    pub fn with_padding(mut self, secs: f32) -> Self {
+        self.padding_in_secs = secs.max(0.0);
+        self
+    }
+
+    pub fn with_max_gap(mut self, secs: Option<f32>) -> Self {
+        self.max_gap_in_secs = secs.map(|secs| secs.max(0.0));
+        self
+    }
+
+    pub fn with_crossfade(mut self, secs: f32) -> Self {
+        self.crossfade_in_secs = secs.max(0.0);
+        self
+    }
+}
+
+/// # Description
+/// cuts the silence before the first word and after the last one out of interleaved `samples`,
+/// and shortens the pauses in between that go on for too long
+/// ## Returns
+/// the interleaved samples that are left, nothing when nobody spoke at all
+/// ## Comments
+/// - speech is found by the same `VoiceActivityDetector` voice activated recording uses
+/// - both sides of a shortened pause are crossfaded, and cut ends fade in and out, so the cuts
+///   don't click
+pub fn trim_silence(
+    samples: &[f32],
+    sample_rate: u32,
+    channels: usize,
+    config: &TrimConfig,
+) -> Vec<f32> {
+    let channels = channels.max(1);
+    let frames = samples.len() / channels;
+    let crossfade = secs_to_frames(config.crossfade_in_secs, sample_rate);
+    let kept = kept_frames(
+        &samples[0..frames * channels],
+        sample_rate,
+        channels,
+        config,
+    );
+
+    let mut trimmed = Vec::with_capacity(kept.iter().map(|range| range.len() * channels).sum());
+    for range in &kept {
+        let piece = &samples[range.start * channels..range.end * channels];
+        let fade = crossfade.min(trimmed.len() / channels).min(range.len()) * channels;
+        let overlap = trimmed.len() - fade;
+        for (k, (left, &right)) in trimmed[overlap..].iter_mut().zip(piece).enumerate() {
+            let t = (k / channels + 1) as f32 / (fade / channels + 1) as f32;
+            *left = *left * (1.0 - t) + right * t;
+        }
+        trimmed.extend_from_slice(&piece[fade..]);
+    }
+
+    let trimmed_frames = trimmed.len() / channels;
+    let fade = crossfade.min(trimmed_frames / 2);
+    let cut_start = kept.first().is_some_and(|range| range.start > 0);
+    let cut_end = kept.last().is_some_and(|range| range.end < frames);
+    for k in 0..fade {
+        let gain = k as f32 / fade as f32;
+        for channel in 0..channels {
+            if cut_start {
+                trimmed[k * channels + channel] *= gain;
+            }
+            if cut_end {
+                trimmed[(trimmed_frames - 1 - k) * channels + channel] *= gain;
+            }
+        }
+    }
+    trimmed
+}
+
+/// # Description
+/// decodes the adhoc recording read from `src`, trims its silence and writes it to `dst`
+/// ## Parameters
+/// - `compression_level` is handed to `AdhocCodec::with_compression_level`, higher is smaller
+///   but lossier
+/// ## Returns
+/// the number of frames before and after trimming
+/// ## Comments
+/// - unlike transcoding, the whole recording is decoded into memory
+pub fn trim_recording<R, W>(
+    src: R,
+    dst: W,
+    config: &TrimConfig,
+    compression_level: u32,
+) -> io::Result<(u64, u64)>
+where
+    R: Read,
+    W: Write,
+{
+    let mut codec = AdhocCodec::load(src)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "not an adhoc recording"))?;
+    let info = codec.info();
+    let channels = info.channels().max(1);
+    let mut samples = Vec::new();
+    let mut buffer = vec![0.0; 1024 * channels];
+    while let Some(n) = codec.decode(&mut buffer) {
+        samples.extend_from_slice(&buffer[0..n]);
+    }
+
+    let trimmed = trim_silence(&samples, info.frequency() as u32, channels, config);
+    let mut codec = AdhocCodec::new()
+        .with_compression_level(compression_level)
+        .with_info(info);
+    for block in trimmed.chunks(1024 * channels) {
+        codec.encode(block);
+    }
+    codec
+        .save_to(dst)
+        .ok_or_else(|| io::Error::other("recording could not be written"))?;
+    Ok((
+        (samples.len() / channels) as u64,
+        (trimmed.len() / channels) as u64,
+    ))
+}
+
+/// # Description
+/// the frames of the recording that are kept, in order and apart from each other
+/// ## Comments
+/// - a shortened pause keeps `max_gap_in_secs` plus the crossfade, split between both sides, so
+///   it lasts `max_gap_in_secs` once both sides are crossfaded
+fn kept_frames(
+    samples: &[f32],
+    sample_rate: u32,
+    channels: usize,
+    config: &TrimConfig,
+) -> Vec<Range<usize>> {
+    let frames = samples.len() / channels;
+    let step = secs_to_frames(STEP_IN_SECS, sample_rate).max(1);
+    let mut detector = VoiceActivityDetector::new(config.vad, sample_rate, channels);
+    let mut speech: Vec<Range<usize>> = Vec::new();
+    for (k, block) in samples.chunks(step * channels).enumerate() {
+        if !detector.process(block) {
+            continue;
+        }
+        let start = k * step;
+        let end = (start + step).min(frames);
+        match speech.last_mut() {
+            Some(last) if last.end == start => last.end = end,
+            _ => speech.push(start..end),
+        }
+    }
+
+    // the detector notices speech one attack late, the release already trails it
+    let before = secs_to_frames(
+        config.padding_in_secs + config.vad.attack_in_secs,
+        sample_rate,
+    );
+    let after = secs_to_frames(config.padding_in_secs, sample_rate);
+    let max_gap = config.max_gap_in_secs.map_or(usize::MAX, |secs| {
+        secs_to_frames(secs, sample_rate) + secs_to_frames(config.crossfade_in_secs, sample_rate)
+    });
+    let mut kept: Vec<Range<usize>> = Vec::new();
+    for range in speech {
+        let range = range.start.saturating_sub(before)..(range.end + after).min(frames);
+        match kept.last_mut() {
+            Some(last) if range.start.saturating_sub(last.end) <= max_gap => {
+                last.end = last.end.max(range.end);
+            }
+            Some(last) => {
+                last.end += max_gap / 2;
+                kept.push(range.start - (max_gap - max_gap / 2)..range.end);
+            }
+            None => kept.push(range),
+        }
+    }
+    kept
+}
+
+fn secs_to_frames(secs: f32, sample_rate: u32) -> usize {
+    (secs.max(0.0) * sample_rate as f32) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 16_000;
+
+    fn tone(secs: f32) -> Vec<f32> {
+        (0..(secs * RATE as f32) as usize)
+            .map(|k| 0.3 * (2.0 * std::f32::consts::PI * 220.0 * k as f32 / RATE as f32).sin())
+            .collect()
+    }
+
+    fn silence(secs: f32) -> Vec<f32> {
+        (0..(secs * RATE as f32) as usize)
+            .map(|k| if k % 2 == 0 { 0.0005 } else { -0.0005 })
+            .collect()
+    }
+
+    fn secs(samples: &[f32]) -> f32 {
+        samples.len() as f32 / RATE as f32
+    }
+
+    #[test]
+    fn silence_is_trimmed_and_long_pauses_are_shortened() {
+        let take = [
+            silence(2.0),
+            tone(1.0),
+            silence(3.0),
+            tone(1.0),
+            silence(2.0),
+        ]
+        .concat();
+
+        // 0.36s before each word, its 0.25s release and 0.3s after it, and a 1s pause
+        let trimmed = trim_silence(&take, RATE, 1, &TrimConfig::default());
+        assert!((secs(&trimmed) - 4.7).abs() < 0.05, "{}", secs(&trimmed));
+        assert_eq!(trimmed[0], 0.0);
+        assert_eq!(trimmed[trimmed.len() - 1], 0.0);
+
+        let kept_pauses = TrimConfig::default().with_max_gap(None);
+        let trimmed = trim_silence(&take, RATE, 1, &kept_pauses);
+        assert!((secs(&trimmed) - 5.85).abs() < 0.05, "{}", secs(&trimmed));
+
+        assert!(trim_silence(&silence(3.0), RATE, 1, &TrimConfig::default()).is_empty());
+
+        let mut codec = AdhocCodec::new().with_info(adhoc_audio::StreamInfo::new(RATE, 1));
+        for block in take.chunks(1024) {
+            codec.encode(block);
+        }
+        let mut bytes = Vec::new();
+        codec.save_to(&mut bytes).expect("serialization failed");
+        let mut trimmed = Vec::new();
+        let (before, after) =
+            trim_recording(&bytes[..], &mut trimmed, &TrimConfig::default(), 4).unwrap();
+        assert_eq!(before, 9 * RATE as u64);
+        assert!((after as f32 / RATE as f32 - 4.7).abs() < 0.05);
+        assert!(trimmed.len() < bytes.len());
+    }
+}
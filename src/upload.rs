@@ -0,0 +1,432 @@
+// Chunked, resumable uploads
+//
+// A recording is cut into segments while it's being recorded, every segment is a
+// self-contained adhoc stream that gets uploaded on its own:
+// - `PUT <url>/<session>/<sequence>` uploads a segment, the server answers with an `UploadAck`
+// - `GET <url>/<session>` answers with an `UploadAck` without uploading anything
+// - `POST <url>/<session>/finish?chunks=<count>` glues the segments back together and answers
+//   with an `UploadComplete`
+//
+// The ack always names the first segment the server is missing, so a client that lost its
+// connection resumes from there.
+
+mod web;
+
+pub use web::*;
+
+use adhoc_audio::{AdhocCodec, StreamInfo, Streamable};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt, mem};
+
+/// length of the segments a recording is cut into
+pub const CHUNK_DURATION_IN_SECS: f32 = 5.0;
+const RETRY_DELAY_IN_MILLIS: i32 = 1000;
+const MAX_RETRY_DELAY_IN_MILLIS: i32 = 30_000;
+/// appended to a recorder's field name to name the form field its chunked recording's id goes in
+pub const RECORDING_ID_FIELD_SUFFIX: &str = "__recording_id";
+
+/// # Description
+/// the server's answer to an uploaded segment (or a status query)
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct UploadAck {
+    pub session: String,
+    /// every segment before this one has been received
+    pub next_sequence: u32,
+}
+
+/// # Description
+/// the server's answer once a recording has been reassembled
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct UploadComplete {
+    pub session: String,
+    /// what the server stored the recording as
+    pub id: String,
+}
+
+/// # Description
+/// how far along an upload is
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct UploadProgress {
+    /// bytes sent so far
+    pub sent: f64,
+    /// bytes to send, `0` when the browser can't tell
+    pub total: f64,
+}
+
+impl UploadProgress {
+    /// # Description
+    /// share of the upload that has been sent, from `0` to `1`
+    /// ## Comments
+    /// - stays at `0` while the total is unknown
+    pub fn fraction(&self) -> f64 {
+        if self.total > 0.0 {
+            (self.sent / self.total).clamp(0.0, 1.0)
+        } else {
+            0.0
+        }
+    }
+}
+
+/// # Description
+/// a single segment of a recording
+#[derive(Clone, Debug)]
+pub struct Chunk {
+    pub sequence: u32,
+    /// a serialized `AdhocCodec`
+    pub bytes: Vec<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum UploadError {
+    /// a segment the recording needs was never uploaded
+    MissingChunk(u32),
+    /// a segment doesn't hold a valid adhoc stream, or its format doesn't match the others
+    BadChunk(u32),
+}
+
+impl fmt::Display for UploadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingChunk(sequence) => write!(f, "chunk {} is missing", sequence),
+            Self::BadChunk(sequence) => write!(f, "chunk {} is not a valid recording", sequence),
+        }
+    }
+}
+
+impl std::error::Error for UploadError {}
+
+/// # Description
+/// session ids end up in urls and file names, so only `[A-Za-z0-9_-]` is allowed
+pub fn is_valid_session_id(session: &str) -> bool {
+    !session.is_empty()
+        && session.len() <= 64
+        && session
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// # Description
+/// the form field a recorder uploading under `field_name` in chunks posts its recording's id in
+pub fn recording_id_field(field_name: &str) -> String {
+    format!("{}{}", field_name, RECORDING_ID_FIELD_SUFFIX)
+}
+
+/// # Description
+/// the field name of the recorder a form field carrying a chunked recording's id belongs to
+/// ## Returns
+/// `None` for every other form field
+pub fn recording_id_field_owner(name: &str) -> Option<&str> {
+    name.strip_suffix(RECORDING_ID_FIELD_SUFFIX)
+        .filter(|field_name| !field_name.is_empty())
+}
+
+/// # Description
+/// whether a request that got `status` back is worth sending again
+/// ## Comments
+/// - timeouts, rate limiting and server side failures usually go away on their own
+pub fn is_transient_status(status: u16) -> bool {
+    matches!(status, 408 | 429) || (500..600).contains(&status)
+}
+
+/// # Description
+/// how long to wait before retrying after `failures` failed attempts in a row
+/// ## Comments
+/// - the delay doubles with every failure, up to half a minute
+pub fn retry_delay_in_millis(failures: u32) -> i32 {
+    RETRY_DELAY_IN_MILLIS
+        .saturating_mul(1 << failures.min(5))
+        .min(MAX_RETRY_DELAY_IN_MILLIS)
+}
+
+pub fn chunk_url(url: &str, session: &str, sequence: u32) -> String {
+    format!("{}/{}/{}", url.trim_end_matches('/'), session, sequence)
+}
+
+pub fn status_url(url: &str, session: &str) -> String {
+    format!("{}/{}", url.trim_end_matches('/'), session)
+}
+
+pub fn finish_url(url: &str, session: &str, chunks: u32) -> String {
+    format!(
+        "{}/{}/finish?chunks={}",
+        url.trim_end_matches('/'),
+        session,
+        chunks
+    )
+}
+
+/// # Description
+/// Cuts a recording into fixed length, independently decodable segments
+/// ## Comments
+/// - segments are cut on block boundaries so they can run slightly over `CHUNK_DURATION_IN_SECS`
+pub struct SegmentEncoder {
+    info: StreamInfo,
+    compression_level: u32,
+    frames_per_segment: usize,
+    codec: AdhocCodec,
+    frames: usize,
+    next_sequence: u32,
+}
+
+impl SegmentEncoder {
+    pub fn new(info: StreamInfo, compression_level: u32) -> Self {
+        Self {
+            info,
+            compression_level,
+            frames_per_segment: 0,
+            codec: Self::new_codec(info, compression_level),
+            frames: 0,
+            next_sequence: 0,
+        }
+        .with_segment_duration(CHUNK_DURATION_IN_SECS)
+    }
+
+    pub fn with_segment_duration(mut self, duration_in_secs: f32) -> Self {
+        self.frames_per_segment =
+            ((duration_in_secs * self.info.frequency() as f32) as usize).max(1);
+        self
+    }
+
+    /// # Description
+    /// numbers segments from `sequence` on, for a recording that carries on where another one stopped
+    pub fn with_first_sequence(mut self, sequence: u32) -> Self {
+        self.next_sequence = sequence;
+        self
+    }
+
+    /// # Description
+    /// encodes interleaved `samples` into the current segment
+    /// ## Returns
+    /// the segment once it's full
+    pub fn encode(&mut self, samples: &[f32]) -> Option<Chunk> {
+        self.codec.encode(samples);
+        self.frames += samples.len() / self.info.channels().max(1);
+        if self.frames >= self.frames_per_segment {
+            self.finish_segment()
+        } else {
+            None
+        }
+    }
+
+    /// # Description
+    /// hands out whatever has been encoded into the current segment so far
+    pub fn flush(&mut self) -> Option<Chunk> {
+        if self.frames > 0 {
+            self.finish_segment()
+        } else {
+            None
+        }
+    }
+
+    /// # Description
+    /// sequence number of the next segment, which is the number of segments handed out so far
+    /// unless `with_first_sequence` was used
+    pub fn segments(&self) -> u32 {
+        self.next_sequence
+    }
+
+    fn finish_segment(&mut self) -> Option<Chunk> {
+        let codec = mem::replace(
+            &mut self.codec,
+            Self::new_codec(self.info, self.compression_level),
+        );
+        self.frames = 0;
+
+        let mut bytes = Vec::new();
+        codec.save_to(&mut bytes)?;
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        Some(Chunk { sequence, bytes })
+    }
+
+    fn new_codec(info: StreamInfo, compression_level: u32) -> AdhocCodec {
+        AdhocCodec::new()
+            .with_compression_level(compression_level)
+            .with_info(info)
+    }
+}
+
+/// # Description
+/// Collects the segments of one recording on the receiving end
+/// ## Comments
+/// - segments may arrive out of order or more than once, a resent segment replaces the old copy
+#[derive(Default)]
+pub struct ChunkAssembler {
+    chunks: BTreeMap<u32, Vec<u8>>,
+    /// level the assembled recording is encoded at, `AdhocCodec`'s default unless set
+    compression_level: u32,
+}
+
+impl ChunkAssembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_compression_level(mut self, compression_level: u32) -> Self {
+        self.compression_level = compression_level;
+        self
+    }
+
+    /// # Description
+    /// stores a segment
+    /// ## Returns
+    /// the sequence number the client should continue from
+    pub fn receive(&mut self, sequence: u32, bytes: Vec<u8>) -> u32 {
+        self.chunks.insert(sequence, bytes);
+        self.next_sequence()
+    }
+
+    /// # Description
+    /// the first segment that hasn't been received yet
+    pub fn next_sequence(&self) -> u32 {
+        let mut next = 0;
+        while self.chunks.contains_key(&next) {
+            next += 1;
+        }
+        next
+    }
+
+    /// # Description
+    /// decodes the first `chunks` segments and encodes them back into a single recording
+    /// ## Comments
+    /// - the recording is meant to be stored with `save_to`, it has to be loaded again before it can be decoded
+    pub fn assemble(&self, chunks: u32) -> Result<AdhocCodec, UploadError> {
+        // don't bother decoding anything when a segment is missing
+        if let Some(missing) = (0..chunks).find(|sequence| !self.chunks.contains_key(sequence)) {
+            return Err(UploadError::MissingChunk(missing));
+        }
+
+        let mut recording: Option<AdhocCodec> = None;
+        let mut buffer = [0.0; 1024];
+        for sequence in 0..chunks.max(1) {
+            let bytes = self
+                .chunks
+                .get(&sequence)
+                .ok_or(UploadError::MissingChunk(sequence))?;
+            let mut segment =
+                AdhocCodec::load(&bytes[..]).ok_or(UploadError::BadChunk(sequence))?;
+            let info = segment.info();
+            let recording = recording.get_or_insert_with(|| {
+                AdhocCodec::new()
+                    .with_compression_level(self.compression_level)
+                    .with_info(info)
+            });
+            if recording.info().frequency() != info.frequency()
+                || recording.info().channels() != info.channels()
+            {
+                return Err(UploadError::BadChunk(sequence));
+            }
+            while let Some(n) = segment.decode(&mut buffer) {
+                recording.encode(&buffer[0..n]);
+            }
+        }
+        recording.ok_or(UploadError::MissingChunk(0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::{record, CaptureSource, SyntheticSource};
+
+    #[test]
+    fn only_contiguous_chunks_are_acknowledged() {
+        let mut assembler = ChunkAssembler::new();
+        assert_eq!(assembler.receive(1, vec![1]), 0);
+        assert_eq!(assembler.receive(0, vec![0]), 2);
+        assert_eq!(assembler.receive(0, vec![0]), 2);
+        assert_eq!(assembler.receive(3, vec![3]), 2);
+        assert!(matches!(
+            assembler.assemble(4),
+            Err(UploadError::MissingChunk(2))
+        ));
+    }
+
+    #[test]
+    fn segments_reassemble_into_the_original_recording() {
+        let mut source = SyntheticSource::sine(220.0, 0.5)
+            .with_sample_rate(8_000)
+            .with_channels(2)
+            .with_duration(2.5);
+        let mut segments = SegmentEncoder::new(source.info(), 4).with_segment_duration(1.0);
+        let mut chunks = Vec::new();
+        let mut reference = AdhocCodec::new().with_info(source.info());
+        record(&mut source, &mut reference, |samples| {
+            chunks.extend(segments.encode(samples));
+            true
+        });
+        chunks.extend(segments.flush());
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(segments.segments(), 3);
+
+        // deliver them out of order, with a resend, the way a flaky connection would
+        let mut assembler = ChunkAssembler::new();
+        for &k in &[2, 0, 0, 1] {
+            let chunk = &chunks[k];
+            assembler.receive(chunk.sequence, chunk.bytes.clone());
+        }
+        assert_eq!(assembler.next_sequence(), 3);
+
+        let assembled = assembler.assemble(3).expect("chunks should reassemble");
+        let mut stored = Vec::new();
+        assembled
+            .save_to(&mut stored)
+            .expect("serialization failed");
+        let mut recording = AdhocCodec::load(&stored[..]).expect("bad recording");
+        assert_eq!(recording.info().channels(), 2);
+        let mut decoded = Vec::new();
+        let mut buffer = [0.0; 1024];
+        while let Some(n) = recording.decode(&mut buffer) {
+            decoded.extend_from_slice(&buffer[0..n]);
+        }
+        assert_eq!(decoded.len(), 2 * 20_000);
+
+        // the segments' level isn't known on the receiving end, it has to be passed through
+        let mut relevelled = Vec::new();
+        assembler
+            .with_compression_level(4)
+            .assemble(3)
+            .expect("chunks should reassemble")
+            .save_to(&mut relevelled)
+            .expect("serialization failed");
+        assert_ne!(relevelled, stored);
+    }
+
+    #[test]
+    fn session_ids_are_url_and_path_safe() {
+        assert!(is_valid_session_id("a1b2-c3_d4"));
+        assert!(!is_valid_session_id(""));
+        assert!(!is_valid_session_id("../etc"));
+        assert!(!is_valid_session_id("a/b"));
+    }
+
+    #[test]
+    fn recording_id_fields_name_their_recorder() {
+        let field = recording_id_field("audio_recording");
+        assert_eq!(recording_id_field_owner(&field), Some("audio_recording"));
+        assert_eq!(recording_id_field_owner("audio_recording"), None);
+        assert_eq!(recording_id_field_owner(RECORDING_ID_FIELD_SUFFIX), None);
+    }
+
+    #[test]
+    fn only_transient_failures_are_retried_with_a_growing_delay() {
+        assert!(is_transient_status(503));
+        assert!(is_transient_status(429));
+        assert!(!is_transient_status(404));
+        assert!(!is_transient_status(422));
+        assert!(!is_transient_status(200));
+
+        let delays = (1..8).map(retry_delay_in_millis).collect::<Vec<_>>();
+        assert!(delays.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(delays[0], 2_000);
+        assert_eq!(*delays.last().unwrap(), MAX_RETRY_DELAY_IN_MILLIS);
+    }
+
+    #[test]
+    fn upload_progress_is_a_fraction_of_the_total() {
+        let progress = |sent, total| UploadProgress { sent, total }.fraction();
+        assert_eq!(progress(250.0, 1000.0), 0.25);
+        assert_eq!(progress(1200.0, 1000.0), 1.0);
+        assert_eq!(progress(500.0, 0.0), 0.0);
+    }
+}
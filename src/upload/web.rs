@@ -0,0 +1,226 @@
+use super::{
+    chunk_url, finish_url, is_transient_status, retry_delay_in_millis, status_url, Chunk,
+    SegmentEncoder, UploadAck, UploadComplete,
+};
+use crate::{recorder::RecorderError, web_utils};
+use adhoc_audio::StreamInfo;
+use serde::de::DeserializeOwned;
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    rc::{Rc, Weak},
+};
+use wasm_bindgen_futures::spawn_local;
+
+/// failed attempts in a row before `finish` gives up
+const MAX_UPLOAD_ATTEMPTS: u32 = 5;
+
+#[derive(Default)]
+struct UploadQueue {
+    /// segments the server hasn't acknowledged yet
+    pending: VecDeque<Chunk>,
+    /// whether a task is currently working through `pending`
+    pumping: bool,
+    /// failed attempts in a row
+    failures: u32,
+    /// the server turned a segment down for good, nothing more gets sent
+    rejected: Option<RecorderError>,
+}
+
+impl UploadQueue {
+    /// # Description
+    /// forgets every segment before `next_sequence`, the server already has them
+    fn acknowledge(&mut self, next_sequence: u32) {
+        while self
+            .pending
+            .front()
+            .map(|chunk| chunk.sequence < next_sequence)
+            .unwrap_or(false)
+        {
+            self.pending.pop_front();
+        }
+    }
+}
+
+/// # Description
+/// Uploads a recording segment by segment while it's being recorded
+/// ## Comments
+/// - segments are sent one at a time in order, a failed segment is retried with a growing delay
+/// - before retrying the server is asked which segment it expects next, so nothing is sent twice
+/// - a segment the server turns down (a `4xx` other than `408` and `429`) fails the whole upload,
+///   there's no point in sending it again
+pub struct ChunkUploader {
+    url: String,
+    session: String,
+    segments: SegmentEncoder,
+    queue: Rc<RefCell<UploadQueue>>,
+}
+
+impl ChunkUploader {
+    /// # Description
+    /// starts a new upload session against `url`
+    pub fn new(url: &str, info: StreamInfo, compression_level: u32) -> Self {
+        Self {
+            url: url.to_string(),
+            session: new_session_id(),
+            segments: SegmentEncoder::new(info, compression_level),
+            queue: Rc::new(RefCell::new(UploadQueue::default())),
+        }
+    }
+
+    pub fn session(&self) -> &str {
+        &self.session
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// # Description
+    /// encodes interleaved `samples`, full segments get uploaded right away
+    pub fn encode(&mut self, samples: &[f32]) {
+        if let Some(chunk) = self.segments.encode(samples) {
+            self.push(chunk);
+        }
+    }
+
+    /// # Description
+    /// uploads what's left of the recording and asks the server to put it back together
+    /// ## Returns
+    /// the id the server stored the recording under
+    pub async fn finish(mut self) -> Result<String, RecorderError> {
+        if let Some(chunk) = self.segments.flush() {
+            self.push(chunk);
+        }
+        loop {
+            let (drained, failures, rejected) = {
+                let queue = self.queue.borrow();
+                (
+                    queue.pending.is_empty() && !queue.pumping,
+                    queue.failures,
+                    queue.rejected.clone(),
+                )
+            };
+            if let Some(err) = rejected {
+                return Err(err);
+            }
+            if drained {
+                break;
+            }
+            if failures >= MAX_UPLOAD_ATTEMPTS {
+                return Err(RecorderError::Upload(format!(
+                    "gave up after {} attempts",
+                    failures
+                )));
+            }
+            web_utils::sleep(100).await;
+        }
+
+        let url = finish_url(&self.url, &self.session, self.segments.segments());
+        let complete: UploadComplete = request_json("POST", &url, None)
+            .await
+            .map_err(|(_, err)| err)?;
+        Ok(complete.id)
+    }
+
+    fn push(&mut self, chunk: Chunk) {
+        let mut queue = self.queue.borrow_mut();
+        queue.pending.push_back(chunk);
+        if !queue.pumping && queue.rejected.is_none() {
+            queue.pumping = true;
+            spawn_local(pump(
+                self.url.clone(),
+                self.session.clone(),
+                Rc::downgrade(&self.queue),
+            ));
+        }
+    }
+}
+
+/// # Description
+/// uploads pending segments until there are none left
+/// ## Comments
+/// - stops once the `ChunkUploader` owning the queue is dropped, or the server turned a segment
+///   down
+async fn pump(url: String, session: String, queue: Weak<RefCell<UploadQueue>>) {
+    while let Some(queue) = queue.upgrade() {
+        let next_chunk = queue.borrow().pending.front().cloned();
+        let chunk = match next_chunk {
+            Some(chunk) => chunk,
+            None => {
+                queue.borrow_mut().pumping = false;
+                return;
+            }
+        };
+        let target = chunk_url(&url, &session, chunk.sequence);
+        match request_json::<UploadAck>("PUT", &target, Some(&chunk.bytes)).await {
+            Ok(ack) => {
+                let mut queue = queue.borrow_mut();
+                queue.failures = 0;
+                queue.acknowledge(ack.next_sequence.max(chunk.sequence + 1));
+            }
+            Err((Some(status), err)) if !is_transient_status(status) => {
+                let mut queue = queue.borrow_mut();
+                queue.rejected = Some(err);
+                queue.pumping = false;
+                return;
+            }
+            Err(_) => {
+                let failures = {
+                    let mut queue = queue.borrow_mut();
+                    queue.failures += 1;
+                    queue.failures
+                };
+                web_utils::sleep(retry_delay_in_millis(failures)).await;
+
+                // resume from whatever the server managed to store
+                let status = status_url(&url, &session);
+                if let Ok(ack) = request_json::<UploadAck>("GET", &status, None).await {
+                    queue.borrow_mut().acknowledge(ack.next_sequence);
+                }
+            }
+        }
+    }
+}
+
+/// # Description
+/// sends a request and reads the json the server answers with
+/// ## Returns
+/// on failure the status the server answered with, `None` when it couldn't be reached (or
+/// answered with something unreadable), along with the error
+async fn request_json<T>(
+    method: &str,
+    url: &str,
+    body: Option<&[u8]>,
+) -> Result<T, (Option<u16>, RecorderError)>
+where
+    T: DeserializeOwned,
+{
+    let (status, response) = web_utils::send_request(method, url, body)
+        .await
+        .map_err(|err| (None, RecorderError::upload(err)))?;
+    if !(200..300).contains(&status) {
+        return Err((
+            Some(status),
+            RecorderError::Upload(format!("server answered with {}: {}", status, response)),
+        ));
+    }
+    serde_json::from_str(&response).map_err(|err| {
+        (
+            None,
+            RecorderError::Upload(format!("unexpected answer: {}", err)),
+        )
+    })
+}
+
+/// # Description
+/// a fresh id that's unique enough to tell uploads (and queued forms) apart
+pub(crate) fn new_session_id() -> String {
+    let random = || (js_sys::Math::random() * u32::MAX as f64) as u32;
+    format!(
+        "{:x}-{:08x}{:08x}",
+        js_sys::Date::now() as u64,
+        random(),
+        random()
+    )
+}
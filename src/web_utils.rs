@@ -1,4 +1,151 @@
-use web_sys::{Element, HtmlCollection};
+use js_sys::{Array, Function, Promise, Uint8Array, WeakSet};
+use wasm_bindgen::{closure::Closure, convert::FromWasmAbi, JsCast, JsValue};
+use wasm_bindgen_futures::{spawn_local, JsFuture};
+use web_sys::{
+    Blob, BlobPropertyBag, Document, Element, FormData, HtmlAnchorElement, HtmlCollection,
+    MutationObserver, MutationObserverInit, MutationRecord, Node, NodeList, ProgressEvent, Url,
+    XmlHttpRequest,
+};
+
+/// how long a downloaded blob's url is kept around, browsers fetch it after `click` returns
+const DOWNLOAD_URL_LIFETIME_IN_MILLIS: i32 = 30_000;
+
+pub fn closure_to_function<CB, T>(cb: CB) -> js_sys::Function
+where
+    T: FromWasmAbi + 'static,
+    CB: FnMut(T) + 'static,
+{
+    Closure::wrap(Box::new(cb) as Box<dyn FnMut(T)>)
+        .into_js_value()
+        .dyn_into::<Function>()
+        .unwrap()
+}
+
+pub fn bytes_to_blob(byte_slice: &[u8]) -> Blob {
+    bytes_to_blob_with_type(byte_slice, "application/octet-stream")
+}
+
+/// # Description
+/// same as `bytes_to_blob` but the blob is tagged with `mime_type`
+pub fn bytes_to_blob_with_type(byte_slice: &[u8], mime_type: &str) -> Blob {
+    let bytes = Array::new();
+    let typed_array = Uint8Array::new_with_length(byte_slice.len() as u32);
+    typed_array.copy_from(byte_slice);
+    bytes.push(&typed_array);
+    let options = BlobPropertyBag::new();
+    options.set_type(mime_type);
+    Blob::new_with_u8_array_sequence_and_options(&bytes, &options).expect("blob failed")
+}
+
+/// # Description
+/// has the browser save `blob` as `file_name`
+/// ## Comments
+/// - firefox and safari start the download asynchronously, so the blob's url is only revoked once
+///   the download had plenty of time to start
+pub fn download_blob(blob: &Blob, file_name: &str) -> Result<(), JsValue> {
+    let document = web_sys::window()
+        .and_then(|window| window.document())
+        .ok_or_else(|| JsValue::from_str("no document"))?;
+    let url = Url::create_object_url_with_blob(blob)?;
+    let link = document
+        .create_element("a")?
+        .dyn_into::<HtmlAnchorElement>()?;
+    link.set_href(&url);
+    link.set_download(file_name);
+    link.click();
+    spawn_local(async move {
+        sleep(DOWNLOAD_URL_LIFETIME_IN_MILLIS).await;
+        Url::revoke_object_url(&url).ok();
+    });
+    Ok(())
+}
+
+/// # Description
+/// sends an http request and waits for it to finish
+/// ## Returns
+/// the status code along with the response body, network failures are errors
+pub async fn send_request(
+    method: &str,
+    url: &str,
+    body: Option<&[u8]>,
+) -> Result<(u16, String), JsValue> {
+    let request = XmlHttpRequest::new()?;
+    request.open(method, url)?;
+    let done = request_done(&request);
+    match body {
+        Some(bytes) => request.send_with_opt_u8_array(Some(bytes))?,
+        None => request.send()?,
+    }
+    wait_for_response(&request, done).await
+}
+
+/// # Description
+/// posts `form_data` as `multipart/form-data` and waits for the answer
+/// ## Parameters
+/// - `on_progress` gets called with the bytes sent so far and the bytes to send (`0` when unknown)
+/// ## Returns
+/// same as `send_request`
+pub async fn send_form_data<CB>(
+    url: &str,
+    form_data: &FormData,
+    mut on_progress: CB,
+) -> Result<(u16, String), JsValue>
+where
+    CB: FnMut(f64, f64) + 'static,
+{
+    let request = XmlHttpRequest::new()?;
+    request.open("POST", url)?;
+    let done = request_done(&request);
+    request
+        .upload()?
+        .set_onprogress(Some(&closure_to_function(move |e: ProgressEvent| {
+            let total = if e.length_computable() {
+                e.total()
+            } else {
+                0.0
+            };
+            on_progress(e.loaded(), total);
+        })));
+    request.send_with_opt_form_data(Some(form_data))?;
+    wait_for_response(&request, done).await
+}
+
+/// # Description
+/// a promise that settles once `request` is over, it rejects when the connection fails
+fn request_done(request: &XmlHttpRequest) -> Promise {
+    Promise::new(&mut |resolve, reject| {
+        request.set_onload(Some(&resolve));
+        request.set_onerror(Some(&reject));
+        request.set_onabort(Some(&reject));
+        request.set_ontimeout(Some(&reject));
+    })
+}
+
+async fn wait_for_response(
+    request: &XmlHttpRequest,
+    done: Promise,
+) -> Result<(u16, String), JsValue> {
+    JsFuture::from(done)
+        .await
+        .map_err(|_| JsValue::from_str("the connection failed"))?;
+    Ok((
+        request.status()?,
+        request.response_text()?.unwrap_or_default(),
+    ))
+}
+
+/// # Description
+/// resolves after `millis` milliseconds
+pub async fn sleep(millis: i32) {
+    let timer = Promise::new(&mut |resolve, _| {
+        if let Some(window) = web_sys::window() {
+            window
+                .set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, millis)
+                .ok();
+        }
+    });
+    JsFuture::from(timer).await.ok();
+}
 
 pub struct ParentIter {
     elem: Element,
@@ -55,3 +202,115 @@ impl Iterator for DomIter {
         elem
     }
 }
+
+/// # Description
+/// Reports elements carrying a class as they get added to or removed from the dom
+/// ## Comments
+/// - elements already in the dom are reported as added when the observer is created
+/// - an element moved within the dom is neither reported as removed nor added again, the elements
+///   reported so far are remembered until they leave the dom
+/// - the observer keeps running until `disconnect` is called, dropping it doesn't stop it
+pub struct ClassObserver {
+    observer: MutationObserver,
+}
+
+impl ClassObserver {
+    /// # Description
+    /// watches the subtree under `root` for elements with the class `class_name`
+    /// ## Parameters
+    /// - `on_added` gets called once for every matching element that shows up
+    /// - `on_removed` gets called once for every matching element that leaves the dom
+    pub fn new<Added, Removed>(
+        root: &Node,
+        class_name: &str,
+        mut on_added: Added,
+        mut on_removed: Removed,
+    ) -> Result<Self, JsValue>
+    where
+        Added: FnMut(Element) + 'static,
+        Removed: FnMut(Element) + 'static,
+    {
+        // a moved element shows up in `addedNodes` while it's already been reported
+        let reported = WeakSet::new();
+        let added = reported.clone();
+        let mut on_added = move |element: Element| {
+            if !added.has(&element) {
+                added.add(&element);
+                on_added(element);
+            }
+        };
+        let mut on_removed = move |element: Element| {
+            if reported.delete(&element) {
+                on_removed(element);
+            }
+        };
+        if let Some(root) = root.dyn_ref::<Element>() {
+            matching_elements(root, class_name).for_each(&mut on_added);
+        } else if let Some(document) = root.dyn_ref::<Document>() {
+            DomIter::new(document.get_elements_by_class_name(class_name))
+                .collect::<Vec<_>>()
+                .into_iter()
+                .for_each(&mut on_added);
+        }
+
+        let class_name = class_name.to_string();
+        let observer = MutationObserver::new(&closure_to_function(move |records: Array| {
+            for record in records.iter() {
+                let record = match record.dyn_into::<MutationRecord>() {
+                    Ok(record) => record,
+                    Err(_) => continue,
+                };
+                // elements that are still connected have only been moved around
+                NodeIter::new(record.removed_nodes())
+                    .filter(|node| !node.is_connected())
+                    .filter_map(|node| node.dyn_into::<Element>().ok())
+                    .flat_map(|element| matching_elements(&element, &class_name))
+                    .for_each(&mut on_removed);
+                NodeIter::new(record.added_nodes())
+                    .filter(|node| node.is_connected())
+                    .filter_map(|node| node.dyn_into::<Element>().ok())
+                    .flat_map(|element| matching_elements(&element, &class_name))
+                    .for_each(&mut on_added);
+            }
+        }))?;
+
+        let options = MutationObserverInit::new();
+        options.set_child_list(true);
+        options.set_subtree(true);
+        observer.observe_with_options(root, &options)?;
+        Ok(Self { observer })
+    }
+
+    pub fn disconnect(&self) {
+        self.observer.disconnect();
+    }
+}
+
+/// # Description
+/// `element` itself (when it carries the class) followed by its matching descendants
+fn matching_elements(element: &Element, class_name: &str) -> impl Iterator<Item = Element> {
+    let this = Some(element.clone()).filter(|e| e.class_list().contains(class_name));
+    // collected up front because `HtmlCollection`s are live
+    let descendants = DomIter::by_class_name(element, class_name).collect::<Vec<_>>();
+    this.into_iter().chain(descendants)
+}
+
+pub struct NodeIter {
+    nodes: NodeList,
+    idx: u32,
+}
+
+impl NodeIter {
+    pub fn new(nodes: NodeList) -> Self {
+        Self { nodes, idx: 0 }
+    }
+}
+
+impl Iterator for NodeIter {
+    type Item = Node;
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.nodes.get(self.idx);
+        self.idx += 1;
+        node
+    }
+}